@@ -0,0 +1,628 @@
+use std::fs::{
+    copy, create_dir_all, hard_link, read_dir, remove_dir_all, remove_file,
+    File, Metadata,
+};
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+use arklib::ARK_FOLDER;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use indicatif::ProgressBar;
+use walkdir::WalkDir;
+
+use crate::error::AppError;
+use crate::models::backup::Compression;
+use crate::util::{confirm, parse_roots, timestamp};
+use crate::ROOTS_CFG_FILENAME;
+
+pub struct BackupDir {
+    pub timestamp: u64,
+    pub path: PathBuf,
+    /// Backups without the roots file are partial or not backups at all
+    pub valid: bool,
+    /// Compression of `backup-<timestamp>.tar.*` archives,
+    /// `None` for backup folders
+    pub archive: Option<Compression>,
+}
+
+/// Backups sorted from the oldest to the newest, both folders and archives
+pub fn list_backups(backups_dir: &Path) -> Vec<BackupDir> {
+    let entries = match read_dir(backups_dir) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+
+    let mut backups = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let name = name.to_str()?;
+            let path = entry.path();
+
+            if path.is_dir() {
+                let timestamp = name.parse::<u64>().ok()?;
+                let valid = path.join(ROOTS_CFG_FILENAME).is_file();
+                return Some(BackupDir {
+                    timestamp,
+                    path,
+                    valid,
+                    archive: None,
+                });
+            }
+
+            let (timestamp, compression) =
+                Compression::parse_archive_name(name)?;
+            let valid = archive_roots(&path, compression).is_some()
+                && archive_complete(&path, compression);
+            Some(BackupDir {
+                timestamp,
+                path,
+                valid,
+                archive: Some(compression),
+            })
+        })
+        .collect::<Vec<_>>();
+    backups.sort_by_key(|backup| backup.timestamp);
+
+    backups
+}
+
+/// Roots recorded in the backup, empty for partial backups
+pub fn backup_roots(backup: &BackupDir) -> Vec<PathBuf> {
+    match backup.archive {
+        Some(compression) => {
+            archive_roots(&backup.path, compression).unwrap_or_default()
+        }
+        None => File::open(backup.path.join(ROOTS_CFG_FILENAME))
+            .map(parse_roots)
+            .unwrap_or_default(),
+    }
+}
+
+/// Total size of the files in the backup, hard links are counted in full.
+/// Archives count with their compressed size.
+pub fn backup_size(backup: &BackupDir) -> u64 {
+    if backup.archive.is_some() {
+        return backup
+            .path
+            .metadata()
+            .map(|metadata| metadata.len())
+            .unwrap_or(0);
+    }
+
+    WalkDir::new(&backup.path)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok())
+        .map(|metadata| metadata.len())
+        .sum()
+}
+
+fn open_archive(
+    path: &Path,
+    compression: Compression,
+) -> Result<tar::Archive<Box<dyn Read>>, AppError> {
+    let file = File::open(path)?;
+    let reader: Box<dyn Read> = match compression {
+        Compression::None => Box::new(file),
+        Compression::Gzip => Box::new(GzDecoder::new(file)),
+        Compression::Zstd => Box::new(zstd::Decoder::new(file)?),
+    };
+
+    Ok(tar::Archive::new(reader))
+}
+
+// The roots file is written first, so only the head of the archive is read
+fn archive_roots(
+    path: &Path,
+    compression: Compression,
+) -> Option<Vec<PathBuf>> {
+    let mut archive = open_archive(path, compression).ok()?;
+    let mut entries = archive.entries().ok()?;
+    let mut first = entries.next()?.ok()?;
+    if first.path().ok()?.as_ref() != Path::new(ROOTS_CFG_FILENAME) {
+        return None;
+    }
+
+    let mut roots = String::new();
+    first.read_to_string(&mut roots).ok()?;
+    Some(roots.lines().map(PathBuf::from).collect())
+}
+
+/// Whether every entry of the archive can be read through to the end,
+/// which takes reading the whole archive
+fn archive_complete(path: &Path, compression: Compression) -> bool {
+    let mut archive = match open_archive(path, compression) {
+        Ok(archive) => archive,
+        Err(_) => return false,
+    };
+    let entries = match archive.entries() {
+        Ok(entries) => entries,
+        Err(_) => return false,
+    };
+
+    for entry in entries {
+        let read = entry.and_then(|mut entry| {
+            std::io::copy(&mut entry, &mut std::io::sink())
+        });
+        if read.is_err() {
+            return false;
+        }
+    }
+
+    true
+}
+
+/// Latest backup made before `current` which contains a copy of `root`
+pub fn previous_backup(
+    backups_dir: &Path,
+    current: u64,
+    root: &Path,
+) -> Option<PathBuf> {
+    list_backups(backups_dir)
+        .into_iter()
+        .rev()
+        // Files can only be hard-linked from backup folders
+        .filter(|backup| {
+            backup.valid
+                && backup.archive.is_none()
+                && backup.timestamp < current
+        })
+        .find_map(|backup| {
+            let roots =
+                File::open(backup.path.join(ROOTS_CFG_FILENAME)).ok()?;
+            let position = parse_roots(roots)
+                .iter()
+                .position(|backed_up| backed_up == root)?;
+
+            let storages = backup.path.join(position.to_string());
+            storages.is_dir().then_some(storages)
+        })
+}
+
+/// Splits valid backups into the ones to keep and the ones to remove:
+/// the `keep_last` newest backups are kept, as well as
+/// all backups younger than `keep_days`
+pub fn prune_plan(
+    backups: Vec<BackupDir>,
+    now: u64,
+    keep_last: usize,
+    keep_days: Option<u64>,
+) -> (Vec<BackupDir>, Vec<BackupDir>) {
+    let valid = backups
+        .into_iter()
+        .filter(|backup| backup.valid)
+        .collect::<Vec<_>>();
+    let count = valid.len();
+
+    let mut keep = Vec::new();
+    let mut remove = Vec::new();
+    for (i, backup) in valid.into_iter().enumerate() {
+        let recent = keep_days
+            .map(|days| now.saturating_sub(backup.timestamp) < days * 86400)
+            .unwrap_or(false);
+
+        if i + keep_last >= count || recent {
+            keep.push(backup);
+        } else {
+            remove.push(backup);
+        }
+    }
+
+    (keep, remove)
+}
+
+pub fn prune_backups(
+    backups_dir: &Path,
+    keep_last: usize,
+    keep_days: Option<u64>,
+    yes: bool,
+    dry_run: bool,
+) -> Result<(), AppError> {
+    let backups = list_backups(backups_dir);
+    let (_, remove) =
+        prune_plan(backups, timestamp().as_secs(), keep_last, keep_days);
+
+    if remove.is_empty() {
+        println!("Nothing to prune.");
+        return Ok(());
+    }
+
+    println!("These backups will be removed:");
+    for backup in &remove {
+        println!("\t{}", backup.path.display());
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+    if !yes && !confirm("Remove them?")? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    for backup in remove {
+        match backup.archive {
+            Some(_) => remove_file(&backup.path)?,
+            None => remove_dir_all(&backup.path)?,
+        }
+    }
+    println!("Done.");
+
+    Ok(())
+}
+
+/// Copies `source` into `destination`, hard-linking the files which
+/// didn't change since the `previous` copy instead of copying them again.
+/// Returns the amount of bytes saved this way.
+pub fn copy_incremental(
+    source: &Path,
+    destination: &Path,
+    previous: &Path,
+    progress: &ProgressBar,
+) -> Result<u64, AppError> {
+    let mut saved = 0;
+
+    for entry in WalkDir::new(source) {
+        let entry =
+            entry.map_err(|e| AppError::BackupCreationError(e.to_string()))?;
+        let relative = entry
+            .path()
+            .strip_prefix(source)
+            .expect("WalkDir yields paths inside of its root");
+        let target = destination.join(relative);
+
+        if entry.file_type().is_dir() {
+            create_dir_all(&target)?;
+            continue;
+        }
+        if !entry.file_type().is_file() {
+            continue;
+        }
+
+        let metadata = entry
+            .metadata()
+            .map_err(|e| AppError::BackupCreationError(e.to_string()))?;
+        let old = previous.join(relative);
+
+        // Filesystems without hard links support fall back to copying
+        if unchanged(&metadata, &old) && hard_link(&old, &target).is_ok() {
+            saved += metadata.len();
+        } else {
+            copy(entry.path(), &target)?;
+        }
+        progress.inc(metadata.len());
+    }
+
+    Ok(saved)
+}
+
+fn unchanged(metadata: &Metadata, old: &Path) -> bool {
+    let old = match old.metadata() {
+        Ok(old) if old.is_file() => old,
+        _ => return false,
+    };
+
+    if old.len() != metadata.len() {
+        return false;
+    }
+
+    // The previous copy was written after the last modification
+    match (metadata.modified(), old.modified()) {
+        (Ok(modified), Ok(copied)) => modified <= copied,
+        _ => false,
+    }
+}
+
+/// Writes the same layout as a backup folder into a single archive:
+/// the roots file and the storages of every root under its number.
+/// The archive gets its name only once it is complete, so that
+/// an interrupted backup is never listed.
+pub fn create_archive(
+    archive_path: &Path,
+    roots: &[PathBuf],
+    compression: Compression,
+) -> Result<(), AppError> {
+    let file_name = archive_path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| {
+            AppError::BackupCreationError(format!(
+                "Invalid archive path: {}",
+                archive_path.display()
+            ))
+        })?;
+    let partial_path =
+        archive_path.with_file_name(format!(".{}.partial", file_name));
+
+    let result =
+        write_compressed(&partial_path, roots, compression).and_then(|_| {
+            std::fs::rename(&partial_path, archive_path).map_err(AppError::from)
+        });
+    if result.is_err() {
+        let _ = remove_file(&partial_path);
+    }
+
+    result
+}
+
+fn write_compressed(
+    path: &Path,
+    roots: &[PathBuf],
+    compression: Compression,
+) -> Result<(), AppError> {
+    let file = File::create(path)?;
+
+    match compression {
+        Compression::None => write_archive(file, roots)?.flush()?,
+        Compression::Gzip => {
+            let encoder = GzEncoder::new(file, flate2::Compression::default());
+            write_archive(encoder, roots)?.finish()?;
+        }
+        Compression::Zstd => {
+            let encoder = zstd::Encoder::new(file, 0)?;
+            write_archive(encoder, roots)?.finish()?;
+        }
+    };
+
+    Ok(())
+}
+
+fn write_archive<W: Write>(
+    writer: W,
+    roots: &[PathBuf],
+) -> Result<W, AppError> {
+    let mut builder = tar::Builder::new(writer);
+
+    let roots_cfg = roots
+        .iter()
+        .map(|root| format!("{}\n", root.display()))
+        .collect::<String>();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(roots_cfg.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(
+        &mut header,
+        ROOTS_CFG_FILENAME,
+        roots_cfg.as_bytes(),
+    )?;
+
+    for (i, root) in roots.iter().enumerate() {
+        println!("\tRoot {}", root.display());
+
+        let result =
+            builder.append_dir_all(i.to_string(), root.join(ARK_FOLDER));
+        if let Err(e) = result {
+            eprintln!("\t\tFailed to copy storages!\n\t\t{}", e);
+        }
+    }
+
+    Ok(builder.into_inner()?)
+}
+
+/// Copies the storages of every root recorded in the backup back into
+/// the root, overwriting the current files of the same names.
+/// Roots which don't exist anymore are skipped.
+pub fn restore_backup(
+    backup: &BackupDir,
+    yes: bool,
+    dry_run: bool,
+) -> Result<(), AppError> {
+    let roots = backup_roots(backup);
+    if roots.is_empty() {
+        return Err(AppError::BackupCreationError(format!(
+            "Backup {} records no roots",
+            backup.path.display()
+        )));
+    }
+
+    println!("Storages of these roots will be restored:");
+    for root in &roots {
+        let missing = if root.is_dir() {
+            ""
+        } else {
+            " (missing, skipped)"
+        };
+        println!("\t{}{}", root.display(), missing);
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+    if !yes && !confirm("Overwrite their storages?")? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    match backup.archive {
+        Some(compression) => restore_archive(backup, compression, &roots)?,
+        None => {
+            for (i, root) in roots.iter().enumerate() {
+                let storages = backup.path.join(i.to_string());
+                if !root.is_dir() || !storages.is_dir() {
+                    continue;
+                }
+                restore_folder(&storages, &root.join(ARK_FOLDER))?;
+            }
+        }
+    }
+    println!("Done.");
+
+    Ok(())
+}
+
+fn restore_folder(source: &Path, destination: &Path) -> Result<(), AppError> {
+    for entry in WalkDir::new(source) {
+        let entry =
+            entry.map_err(|e| AppError::BackupCreationError(e.to_string()))?;
+        let relative = entry
+            .path()
+            .strip_prefix(source)
+            .expect("WalkDir yields paths inside of its root");
+        let target = destination.join(relative);
+
+        if entry.file_type().is_dir() {
+            create_dir_all(&target)?;
+        } else if entry.file_type().is_file() {
+            copy(entry.path(), &target)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn restore_archive(
+    backup: &BackupDir,
+    compression: Compression,
+    roots: &[PathBuf],
+) -> Result<(), AppError> {
+    let mut archive = open_archive(&backup.path, compression)?;
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+
+        // Backups only hold files and directories, links could point
+        // the following entries outside of the root
+        let kind = entry.header().entry_type();
+        if !kind.is_file() && !kind.is_dir() {
+            eprintln!("Skipping {}, it is not a file", path.display());
+            continue;
+        }
+
+        // Entries are `<root number>/<path inside .ark>`, anything else,
+        // like the roots file, is not restored
+        let mut components = path.components();
+        let root = match components.next() {
+            Some(Component::Normal(number)) => number
+                .to_str()
+                .and_then(|number| number.parse::<usize>().ok())
+                .and_then(|number| roots.get(number)),
+            _ => None,
+        };
+        let root = match root {
+            Some(root) if root.is_dir() => root,
+            _ => continue,
+        };
+
+        let relative = components.as_path();
+        if relative
+            .components()
+            .any(|component| !matches!(component, Component::Normal(_)))
+        {
+            return Err(AppError::BackupCreationError(format!(
+                "Archive entry leaves its root: {}",
+                path.display()
+            )));
+        }
+
+        let ark = root.join(ARK_FOLDER);
+        let target = ark.join(relative);
+        if let Some(parent) = target.parent() {
+            create_dir_all(parent)?;
+            // Links already present in the root are not followed either
+            if !parent
+                .canonicalize()?
+                .starts_with(ark.canonicalize()?)
+            {
+                return Err(AppError::BackupCreationError(format!(
+                    "Archive entry leaves its root: {}",
+                    path.display()
+                )));
+            }
+        }
+        let is_link = target
+            .symlink_metadata()
+            .is_ok_and(|metadata| metadata.file_type().is_symlink());
+        if is_link {
+            remove_file(&target)?;
+        }
+        entry.unpack(&target)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn root_with_storage(dir: &Path) -> PathBuf {
+        let root = dir.join("root");
+        create_dir_all(root.join(ARK_FOLDER).join("user")).unwrap();
+        std::fs::write(root.join(ARK_FOLDER).join("user/tags"), "1-1:a\n")
+            .unwrap();
+        root
+    }
+
+    #[test]
+    fn truncated_archive_is_invalid() {
+        let dir = TempDir::new().unwrap();
+        let root = root_with_storage(dir.path());
+        let backups = dir.path().join("backups");
+        create_dir_all(&backups).unwrap();
+
+        let archive = backups.join("backup-1.tar.gz");
+        create_archive(&archive, &[root], Compression::Gzip).unwrap();
+        let names = read_dir(&backups)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["backup-1.tar.gz"]);
+        assert!(list_backups(&backups)[0].valid);
+
+        let length = archive.metadata().unwrap().len();
+        File::options()
+            .write(true)
+            .open(&archive)
+            .unwrap()
+            .set_len(length / 2)
+            .unwrap();
+        assert!(!list_backups(&backups)[0].valid);
+    }
+
+    #[test]
+    fn restore_skips_links() {
+        let dir = TempDir::new().unwrap();
+        let root = root_with_storage(dir.path());
+        let outside = dir.path().join("outside");
+        create_dir_all(&outside).unwrap();
+
+        let path = dir.path().join("backup-1.tar");
+        let mut builder = tar::Builder::new(File::create(&path).unwrap());
+        let mut header = tar::Header::new_gnu();
+        header.set_entry_type(tar::EntryType::Symlink);
+        header.set_size(0);
+        header.set_link_name(&outside).unwrap();
+        builder
+            .append_data(&mut header, "0/x", std::io::empty())
+            .unwrap();
+        let mut header = tar::Header::new_gnu();
+        header.set_size(6);
+        header.set_mode(0o644);
+        builder
+            .append_data(&mut header, "0/x/passwd", "pwned\n".as_bytes())
+            .unwrap();
+        builder.into_inner().unwrap();
+
+        let backup = BackupDir {
+            timestamp: 1,
+            path,
+            valid: true,
+            archive: Some(Compression::None),
+        };
+        restore_archive(&backup, Compression::None, &[root.clone()]).unwrap();
+
+        assert!(!outside.join("passwd").exists());
+        let restored = root.join(ARK_FOLDER).join("x");
+        assert!(!restored
+            .symlink_metadata()
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert!(restored.join("passwd").is_file());
+    }
+}
@@ -0,0 +1,239 @@
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::fs::{create_dir_all, rename, File};
+use std::io::{stdin, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use arklib::id::ResourceId;
+use arklib::ARK_FOLDER;
+
+use crate::error::AppError;
+use crate::models::collisions::Resolution;
+use crate::util::{provide_index, timestamp};
+
+/// Every run moves copies into its own `trash/<timestamp>`,
+/// so that copies moved by earlier runs are never replaced
+pub const TRASH_FOLDER: &str = "trash";
+
+#[derive(Default)]
+pub struct Resolved {
+    /// Kept path of every resolved id
+    pub kept: HashMap<ResourceId, PathBuf>,
+    /// Copies moved into the trash, with their new paths
+    pub moved: Vec<(PathBuf, PathBuf)>,
+    /// Ids which still have more than one path
+    pub unresolved: Vec<ResourceId>,
+}
+
+pub fn find_collisions(root: &PathBuf) -> Vec<(ResourceId, Vec<PathBuf>)> {
+    let index = provide_index(root);
+
+    let mut groups: HashMap<ResourceId, Vec<PathBuf>> = HashMap::new();
+    for (path, entry) in index.path2id.iter() {
+        groups
+            .entry(entry.id)
+            .or_default()
+            .push(path.as_path().to_path_buf());
+    }
+
+    let mut collisions = groups
+        .into_iter()
+        .filter(|(_, paths)| paths.len() > 1)
+        .map(|(id, mut paths)| {
+            paths.sort();
+            (id, paths)
+        })
+        .collect::<Vec<_>>();
+    // The biggest duplicates free the most space, the oldest go first
+    // among the equally big ones
+    collisions.sort_by_cached_key(|(id, paths)| {
+        let metadata = paths
+            .first()
+            .and_then(|path| path.metadata().ok());
+        let size = metadata
+            .as_ref()
+            .map_or(0, |metadata| metadata.len());
+        let modified = metadata.and_then(|metadata| metadata.modified().ok());
+        (Reverse(size), modified, id.to_string())
+    });
+
+    collisions
+}
+
+/// Moves redundant copies into `.ark/trash/<timestamp>`,
+/// keeping their paths relative to the root
+pub fn resolve_collisions(
+    root: &Path,
+    collisions: &[(ResourceId, Vec<PathBuf>)],
+    resolution: Resolution,
+) -> Result<Resolved, AppError> {
+    let root = root.canonicalize()?;
+    let trash = root
+        .join(ARK_FOLDER)
+        .join(TRASH_FOLDER)
+        .join(timestamp().as_millis().to_string());
+
+    let mut resolved = Resolved::default();
+    for (id, paths) in collisions {
+        let keep = match resolution {
+            Resolution::KeepFirst => Some(0),
+            Resolution::KeepOldest => Some(by_modified(paths, false)?),
+            Resolution::KeepNewest => Some(by_modified(paths, true)?),
+            Resolution::Interactive => choose_path(*id, paths)?,
+        };
+
+        let keep = match keep {
+            Some(keep) => &paths[keep],
+            None => {
+                resolved.unresolved.push(*id);
+                continue;
+            }
+        };
+        resolved.kept.insert(*id, keep.to_owned());
+
+        let mut moved_all = true;
+        for path in paths.iter().filter(|path| *path != keep) {
+            // Equal ids don't guarantee equal content
+            if !same_content(keep, path)? {
+                eprintln!(
+                    "Not a true duplicate, leaving in place: {}",
+                    path.display()
+                );
+                moved_all = false;
+                continue;
+            }
+
+            let relative = path.strip_prefix(&root).unwrap_or(path);
+            let destination = trash.join(relative);
+            // `rename` would replace it
+            if destination.exists() {
+                return Err(AppError::FileOperationError(format!(
+                    "{} is in the trash already",
+                    destination.display()
+                )));
+            }
+            if let Some(parent) = destination.parent() {
+                create_dir_all(parent)?;
+            }
+            rename(path, &destination)?;
+            resolved
+                .moved
+                .push((path.to_owned(), destination));
+        }
+
+        if !moved_all {
+            resolved.unresolved.push(*id);
+        }
+    }
+
+    Ok(resolved)
+}
+
+/// Index of the least or the most recently modified path
+fn by_modified(paths: &[PathBuf], newest: bool) -> Result<usize, AppError> {
+    let mut times = Vec::new();
+    for path in paths {
+        times.push(path.metadata()?.modified()?);
+    }
+
+    let mut chosen = 0;
+    for (i, time) in times.iter().enumerate() {
+        if (newest && *time > times[chosen])
+            || (!newest && *time < times[chosen])
+        {
+            chosen = i;
+        }
+    }
+
+    Ok(chosen)
+}
+
+fn choose_path(
+    id: ResourceId,
+    paths: &[PathBuf],
+) -> Result<Option<usize>, AppError> {
+    println!("Id {} is shared by:", id);
+    for (i, path) in paths.iter().enumerate() {
+        println!("\t[{}] {}", i + 1, path.display());
+    }
+
+    loop {
+        println!("Which one to keep? (1-{}, s to skip)", paths.len());
+
+        let mut answer = String::new();
+        if stdin().lock().read_line(&mut answer)? == 0 {
+            return Ok(None);
+        }
+
+        let answer = answer.trim();
+        if answer == "s" {
+            return Ok(None);
+        }
+        match answer.parse::<usize>() {
+            Ok(n) if (1..=paths.len()).contains(&n) => return Ok(Some(n - 1)),
+            _ => println!("Invalid choice: {}", answer),
+        }
+    }
+}
+
+fn same_content(a: &Path, b: &Path) -> Result<bool, AppError> {
+    if a.metadata()?.len() != b.metadata()?.len() {
+        return Ok(false);
+    }
+
+    let mut a = BufReader::new(File::open(a)?);
+    let mut b = BufReader::new(File::open(b)?);
+    let mut buf_a = [0; 8192];
+    let mut buf_b = [0; 8192];
+
+    loop {
+        let read = a.read(&mut buf_a)?;
+        if read == 0 {
+            return Ok(true);
+        }
+        b.read_exact(&mut buf_b[..read])?;
+        if buf_a[..read] != buf_b[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn update_index(root: &Path) {
+        let rwlock = arklib::provide_index(root).unwrap();
+        rwlock.write().unwrap().update_all().unwrap();
+    }
+
+    #[test]
+    fn every_run_moves_into_its_own_trash() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        let trash = root.join(ARK_FOLDER).join(TRASH_FOLDER);
+
+        for run in 0..2 {
+            std::fs::write(root.join("a.txt"), "same").unwrap();
+            std::fs::write(root.join("b.txt"), "same").unwrap();
+            update_index(&root);
+
+            let collisions = find_collisions(&root);
+            assert_eq!(collisions.len(), 1);
+            let resolved =
+                resolve_collisions(&root, &collisions, Resolution::KeepFirst)
+                    .unwrap();
+
+            assert!(resolved.unresolved.is_empty());
+            assert_eq!(resolved.kept[&collisions[0].0], root.join("a.txt"));
+            assert_eq!(resolved.moved.len(), 1);
+            assert_eq!(resolved.moved[0].0, root.join("b.txt"));
+            assert!(resolved.moved[0].1.starts_with(&trash));
+            assert!(resolved.moved[0].1.ends_with("b.txt"));
+            assert_eq!(std::fs::read_dir(&trash).unwrap().count(), run + 1);
+
+            std::thread::sleep(std::time::Duration::from_millis(5));
+        }
+    }
+}
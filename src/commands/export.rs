@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use arklib::id::ResourceId;
+use serde_json::{Map, Value};
+
+use crate::commands::id::parse_id;
+use crate::commands::migrate::USER_STORAGES;
+use crate::commands::storage::open_storage;
+use crate::error::AppError;
+use crate::util::{provide_index, translate_storage};
+
+/// Bumped when the layout of the document changes
+pub const EXPORT_VERSION: u64 = 1;
+
+/// Values of the user storages which were written at least once
+fn read_user_storages(
+    root: &PathBuf,
+) -> Result<HashMap<&'static str, HashMap<ResourceId, String>>, AppError> {
+    let mut storages = HashMap::new();
+    for name in USER_STORAGES {
+        match translate_storage(&Some(root.to_owned()), name) {
+            Some((path, _)) if path.exists() => {}
+            _ => continue,
+        }
+
+        let entries = open_storage(root, name, &None)?
+            .entries()?
+            .into_iter()
+            .collect();
+        storages.insert(name, entries);
+    }
+
+    Ok(storages)
+}
+
+/// Metadata of every indexed resource keyed by id, with the paths
+/// relative to the root. Keys are sorted, so exports of the same
+/// root can be compared.
+pub fn export_metadata(root: &PathBuf) -> Result<Value, AppError> {
+    let canonical_root = root.canonicalize()?;
+    let index = provide_index(root);
+    let storages = read_user_storages(root)?;
+
+    let mut resources = Map::new();
+    for (path, entry) in index.path2id.iter() {
+        let relative = path
+            .as_path()
+            .strip_prefix(&canonical_root)
+            .unwrap_or(path.as_path())
+            .display()
+            .to_string();
+
+        let resource = resources
+            .entry(entry.id.to_string())
+            .or_insert_with(|| resource_metadata(&storages, entry.id));
+        if let Some(Value::Array(paths)) = resource.get_mut("paths") {
+            paths.push(Value::String(relative));
+            paths.sort_by_key(|path| path.to_string());
+        }
+    }
+
+    Ok(serde_json::json!({
+        "version": EXPORT_VERSION,
+        "resources": resources,
+    }))
+}
+
+fn resource_metadata(
+    storages: &HashMap<&'static str, HashMap<ResourceId, String>>,
+    id: ResourceId,
+) -> Value {
+    let mut resource = serde_json::json!({ "paths": [] });
+
+    let value = |name: &str| {
+        storages
+            .get(name)
+            .and_then(|values| values.get(&id))
+    };
+    if let Some(tags) = value("tags") {
+        resource["tags"] = tags
+            .split(',')
+            .map(|tag| tag.trim())
+            .filter(|tag| !tag.is_empty())
+            .collect::<Vec<_>>()
+            .into();
+    }
+    if let Some(score) = value("scores") {
+        resource["score"] = match score.trim().parse::<i64>() {
+            Ok(score) => score.into(),
+            Err(_) => score.clone().into(),
+        };
+    }
+    if let Some(properties) = value("properties") {
+        resource["properties"] = serde_json::from_str(properties)
+            .unwrap_or_else(|_| Value::String(properties.clone()));
+    }
+
+    resource
+}
+
+pub struct ImportReport {
+    /// Entries written per storage
+    pub written: Vec<(&'static str, usize)>,
+    /// Imported ids which are not in the index of the root
+    pub unindexed: Vec<ResourceId>,
+}
+
+/// Writes the metadata of an export into the storages of the root.
+/// With `merge`, tags are united, the higher score is kept and existing
+/// properties are kept, otherwise imported values replace existing ones.
+pub fn import_metadata(
+    root: &PathBuf,
+    export: &Value,
+    merge: bool,
+) -> Result<ImportReport, AppError> {
+    match export.get("version").and_then(Value::as_u64) {
+        Some(EXPORT_VERSION) => {}
+        version => {
+            return Err(AppError::ImportError(format!(
+                "unsupported export version {}",
+                version.map_or("none".to_owned(), |v| v.to_string())
+            )))
+        }
+    }
+    let resources = export
+        .get("resources")
+        .and_then(Value::as_object)
+        .ok_or_else(|| {
+            AppError::ImportError("missing the resources object".to_owned())
+        })?;
+
+    let indexed = provide_index(root).id2path;
+    let existing = if merge {
+        read_user_storages(root)?
+    } else {
+        HashMap::new()
+    };
+    let existing_value = |name: &str, id: &ResourceId| {
+        existing
+            .get(name)
+            .and_then(|values| values.get(id))
+    };
+
+    let mut values: HashMap<&'static str, Vec<(ResourceId, String)>> =
+        HashMap::new();
+    let mut unindexed = Vec::new();
+    for (id, resource) in resources {
+        let id = parse_id(id)?;
+        if !indexed.contains_key(&id) {
+            unindexed.push(id);
+        }
+
+        if let Some(tags) = resource.get("tags").and_then(Value::as_array) {
+            let mut tags = tags
+                .iter()
+                .filter_map(Value::as_str)
+                .map(|tag| tag.to_owned())
+                .collect::<Vec<_>>();
+            if let Some(current) = existing_value("tags", &id) {
+                for tag in current.split(',').map(|tag| tag.trim()) {
+                    if !tag.is_empty() && !tags.iter().any(|t| t == tag) {
+                        tags.push(tag.to_owned());
+                    }
+                }
+            }
+            values
+                .entry("tags")
+                .or_default()
+                .push((id, tags.join(",")));
+        }
+
+        if let Some(score) = resource.get("score") {
+            let score = match score {
+                Value::String(score) => score.clone(),
+                score => score.to_string(),
+            };
+            let higher = match existing_value("scores", &id) {
+                Some(current) => {
+                    match (current.trim().parse::<i64>(), score.parse::<i64>())
+                    {
+                        (Ok(current), Ok(new)) if current >= new => None,
+                        _ => Some(score),
+                    }
+                }
+                None => Some(score),
+            };
+            if let Some(score) = higher {
+                values
+                    .entry("scores")
+                    .or_default()
+                    .push((id, score));
+            }
+        }
+
+        if let Some(properties) = resource.get("properties") {
+            let properties =
+                match (properties, existing_value("properties", &id)) {
+                    (Value::Object(imported), Some(current)) => {
+                        match serde_json::from_str(current) {
+                            Ok(Value::Object(mut current)) => {
+                                for (key, value) in imported {
+                                    current
+                                        .entry(key.clone())
+                                        .or_insert_with(|| value.clone());
+                                }
+                                Value::Object(current).to_string()
+                            }
+                            _ => properties.to_string(),
+                        }
+                    }
+                    (Value::String(properties), _) => properties.clone(),
+                    (properties, _) => properties.to_string(),
+                };
+            values
+                .entry("properties")
+                .or_default()
+                .push((id, properties));
+        }
+    }
+    unindexed.sort_by_key(|id| id.to_string());
+
+    let mut written = Vec::new();
+    for name in USER_STORAGES {
+        let entries = match values.get(name) {
+            Some(entries) => entries,
+            None => continue,
+        };
+
+        let mut storage = open_storage(root, name, &None)?;
+        let mut batch = storage.begin_batch();
+        for (id, value) in entries {
+            batch.insert(*id, value)?;
+        }
+        written.push((name, batch.commit()?));
+    }
+
+    Ok(ImportReport { written, unindexed })
+}
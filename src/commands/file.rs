@@ -1,5 +1,9 @@
+use std::path::Path;
+
 use crate::error::AppError;
+use crate::models::storage::StorageType;
 use crate::models::{format, format::Format};
+use arklib::id::ResourceId;
 use arklib::{modify, modify_json, AtomicFile, Result as ArklibResult};
 
 pub fn file_append(
@@ -51,6 +55,78 @@ pub fn file_insert(
     }
 }
 
+/// Looks up a dotted path like `meta.author` in a JSON value,
+/// scalars are returned without quotes
+pub fn extract_key(value: &str, key: &str) -> Result<String, AppError> {
+    let value: serde_json::Value =
+        serde_json::from_str(value).map_err(|e| {
+            AppError::InvalidInput(format!("the value is not JSON, {}", e))
+        })?;
+
+    lookup_key(&value, key)
+}
+
+pub fn lookup_key(
+    value: &serde_json::Value,
+    key: &str,
+) -> Result<String, AppError> {
+    let mut field = value;
+    let mut parent = "the value".to_owned();
+    for name in key.split('.') {
+        let object = field.as_object().ok_or_else(|| {
+            AppError::InvalidInput(format!(
+                "{} is {}, not an object",
+                parent,
+                json_type(field)
+            ))
+        })?;
+        field = object
+            .get(name)
+            .ok_or_else(|| AppError::KeyNotFound(key.to_owned()))?;
+        parent = format!("'{}'", name);
+    }
+
+    Ok(match field {
+        serde_json::Value::String(field) => field.clone(),
+        field => field.to_string(),
+    })
+}
+
+fn json_type(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "a boolean",
+        serde_json::Value::Number(_) => "a number",
+        serde_json::Value::String(_) => "a string",
+        serde_json::Value::Array(_) => "an array",
+        serde_json::Value::Object(_) => "an object",
+    }
+}
+
+/// Describes the write instead of performing it, for `--dry-run`
+pub fn dry_run_write(
+    action: &str,
+    id: ResourceId,
+    content: &str,
+    format: Format,
+    storage: &Path,
+    storage_type: StorageType,
+) {
+    let target = match storage_type {
+        StorageType::File => storage.to_path_buf(),
+        StorageType::Folder => storage.join(id.to_string()),
+    };
+
+    println!(
+        "Would {} {} bytes of {} to {} in {}",
+        action,
+        content.len(),
+        format,
+        id,
+        target.display()
+    );
+}
+
 fn append_json(
     atomic_file: &AtomicFile,
     data: Vec<(String, String)>,
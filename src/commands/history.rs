@@ -0,0 +1,386 @@
+use std::collections::HashMap;
+use std::fs::{read_to_string, rename, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use arklib::id::ResourceId;
+use arklib::ARK_FOLDER;
+use serde::Deserialize;
+
+use crate::error::AppError;
+use crate::util::{index_snapshot, timestamp, IndexEvent};
+
+const HISTORY_FILE: &str = "index-history";
+const HISTORY_MAX_SIZE: u64 = 1024 * 1024;
+
+#[derive(Deserialize)]
+pub struct HistoryRecord {
+    pub time: u64,
+    pub kind: String,
+    pub id: String,
+    pub old_id: Option<String>,
+    /// Relative to the root
+    pub path: PathBuf,
+}
+
+/// Records older than `truncated_before` could have been dropped
+/// to keep the history file within its size limit
+pub struct History {
+    pub truncated_before: Option<u64>,
+    pub records: Vec<HistoryRecord>,
+}
+
+fn history_path(root: &Path) -> PathBuf {
+    root.join(ARK_FOLDER).join(HISTORY_FILE)
+}
+
+/// Appends the events of one index update in a single write
+pub fn record_events(
+    root: &Path,
+    events: &[IndexEvent],
+) -> Result<(), AppError> {
+    if events.is_empty() {
+        return Ok(());
+    }
+
+    let root = root.canonicalize()?;
+    let time = timestamp().as_secs();
+
+    let mut batch = String::new();
+    for event in events {
+        let mut record = event.to_json();
+        let path = event
+            .path
+            .strip_prefix(&root)
+            .unwrap_or(&event.path);
+        record["path"] = path.display().to_string().into();
+        record["time"] = time.into();
+        batch.push_str(&format!("{}\n", record));
+    }
+
+    let path = history_path(&root);
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?;
+    file.write_all(batch.as_bytes())?;
+
+    if file.metadata()?.len() > HISTORY_MAX_SIZE {
+        truncate_history(&path)?;
+    }
+
+    Ok(())
+}
+
+/// Drops the older half of the records, remembering where the gap starts
+fn truncate_history(path: &Path) -> Result<(), AppError> {
+    let data = read_to_string(path)?;
+    let records = data
+        .lines()
+        .filter(|line| !line.contains("\"truncated_before\""))
+        .collect::<Vec<_>>();
+
+    let kept = &records[records.len() / 2..];
+    let truncated_before = kept
+        .first()
+        .and_then(|line| serde_json::from_str::<HistoryRecord>(line).ok())
+        .map(|record| record.time)
+        .unwrap_or_else(|| timestamp().as_secs());
+
+    let mut output =
+        serde_json::json!({ "truncated_before": truncated_before }).to_string();
+    output.push('\n');
+    for line in kept {
+        output.push_str(line);
+        output.push('\n');
+    }
+
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, output)?;
+    rename(tmp, path)?;
+
+    Ok(())
+}
+
+pub fn read_history(root: &Path) -> Result<History, AppError> {
+    let path = history_path(root);
+    if !path.exists() {
+        return Ok(History {
+            truncated_before: None,
+            records: vec![],
+        });
+    }
+
+    let mut truncated_before = None;
+    let mut records = Vec::new();
+    for (i, line) in read_to_string(path)?.lines().enumerate() {
+        let value: serde_json::Value = match serde_json::from_str(line) {
+            Ok(value) => value,
+            Err(_) => {
+                eprintln!("Skipping malformed history line {}", i + 1);
+                continue;
+            }
+        };
+
+        if let Some(time) = value["truncated_before"].as_u64() {
+            truncated_before = Some(time);
+        } else {
+            match serde_json::from_value::<HistoryRecord>(value) {
+                Ok(record) => records.push(record),
+                Err(_) => {
+                    eprintln!("Skipping malformed history line {}", i + 1)
+                }
+            }
+        }
+    }
+
+    Ok(History {
+        truncated_before,
+        records,
+    })
+}
+
+/// Reconstructs the index as of `until` by undoing the later transitions.
+/// Returns relative paths with their ids and whether the result is
+/// incomplete because the needed records were already dropped.
+pub fn replay_history(
+    root: &PathBuf,
+    until: u64,
+) -> Result<(Vec<(PathBuf, ResourceId)>, bool), AppError> {
+    let canonical_root = root.canonicalize()?;
+    let history = read_history(root)?;
+
+    let index = crate::util::provide_index(root);
+    let membership = index_snapshot(&index)
+        .into_iter()
+        .map(|(path, id)| {
+            let path = path
+                .strip_prefix(&canonical_root)
+                .map(|path| path.to_path_buf())
+                .unwrap_or(path);
+            (path, id)
+        })
+        .collect::<HashMap<_, _>>();
+
+    let membership = undo_records(membership, &history.records, until)?;
+    let gap = history
+        .truncated_before
+        .map(|truncated_before| until < truncated_before)
+        .unwrap_or(false);
+
+    let mut membership = membership.into_iter().collect::<Vec<_>>();
+    membership.sort();
+
+    Ok((membership, gap))
+}
+
+/// Undoes the transitions recorded after `until`, newest first
+fn undo_records(
+    mut membership: HashMap<PathBuf, ResourceId>,
+    records: &[HistoryRecord],
+    until: u64,
+) -> Result<HashMap<PathBuf, ResourceId>, AppError> {
+    let mut records = records
+        .iter()
+        .filter(|record| record.time > until)
+        .collect::<Vec<_>>();
+    records.sort_by_key(|record| record.time);
+
+    for record in records.into_iter().rev() {
+        let id = ResourceId::from_str(&record.id)?;
+        match record.kind.as_str() {
+            "added" => {
+                membership.remove(&record.path);
+            }
+            "removed" => {
+                membership.insert(record.path.clone(), id);
+            }
+            "updated" => {
+                if let Some(old_id) = &record.old_id {
+                    membership.insert(
+                        record.path.clone(),
+                        ResourceId::from_str(old_id)?,
+                    );
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(membership)
+}
+
+/// Filters recorded transitions by path glob, id and time
+pub fn query_history(
+    root: &Path,
+    path: &Option<String>,
+    id: &Option<ResourceId>,
+    since: Option<u64>,
+) -> Result<Vec<HistoryRecord>, AppError> {
+    let pattern = path
+        .as_ref()
+        .map(|path| glob::Pattern::new(path))
+        .transpose()
+        .map_err(|e| AppError::IndexError(e.to_string()))?;
+    let id = id.map(|id| id.to_string());
+
+    let mut records = read_history(root)?
+        .records
+        .into_iter()
+        .filter(|record| match &pattern {
+            Some(pattern) => pattern.matches_path(&record.path),
+            None => true,
+        })
+        .filter(|record| match &id {
+            Some(id) => &record.id == id || record.old_id.as_ref() == Some(id),
+            None => true,
+        })
+        .filter(|record| {
+            since
+                .map(|since| record.time >= since)
+                .unwrap_or(true)
+        })
+        .collect::<Vec<_>>();
+    records.sort_by_key(|record| record.time);
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(time: u64, kind: &str, id: &str, path: &str) -> HistoryRecord {
+        HistoryRecord {
+            time,
+            kind: kind.to_owned(),
+            id: id.to_owned(),
+            old_id: None,
+            path: PathBuf::from(path),
+        }
+    }
+
+    fn id(id: &str) -> ResourceId {
+        ResourceId::from_str(id).unwrap()
+    }
+
+    fn write_history(lines: &[&str]) -> tempfile::TempDir {
+        let root = tempfile::tempdir().unwrap();
+        std::fs::create_dir(root.path().join(ARK_FOLDER)).unwrap();
+        std::fs::write(history_path(root.path()), lines.join("\n")).unwrap();
+        root
+    }
+
+    #[test]
+    fn undo_records_reverts_later_transitions() {
+        let mut updated = record(30, "updated", "3-300", "b.txt");
+        updated.old_id = Some("2-200".to_owned());
+        let records = vec![
+            record(10, "added", "1-100", "a.txt"),
+            record(20, "added", "2-200", "b.txt"),
+            updated,
+            record(40, "removed", "1-100", "a.txt"),
+            record(50, "added", "4-400", "c.txt"),
+        ];
+        let current = HashMap::from([
+            (PathBuf::from("b.txt"), id("3-300")),
+            (PathBuf::from("c.txt"), id("4-400")),
+        ]);
+
+        let at_25 = undo_records(current.clone(), &records, 25).unwrap();
+        assert_eq!(
+            at_25,
+            HashMap::from([
+                (PathBuf::from("a.txt"), id("1-100")),
+                (PathBuf::from("b.txt"), id("2-200")),
+            ])
+        );
+
+        let at_5 = undo_records(current.clone(), &records, 5).unwrap();
+        assert!(at_5.is_empty());
+
+        let at_50 = undo_records(current.clone(), &records, 50).unwrap();
+        assert_eq!(at_50, current);
+    }
+
+    #[test]
+    fn undo_records_orders_by_time() {
+        // Out of order in the file, a re-added path ends up removed
+        let records = vec![
+            record(30, "added", "1-100", "a.txt"),
+            record(20, "removed", "1-100", "a.txt"),
+        ];
+        let current = HashMap::from([(PathBuf::from("a.txt"), id("1-100"))]);
+
+        let at_25 = undo_records(current.clone(), &records, 25).unwrap();
+        assert!(at_25.is_empty());
+
+        let at_10 = undo_records(current, &records, 10).unwrap();
+        assert_eq!(
+            at_10,
+            HashMap::from([(PathBuf::from("a.txt"), id("1-100"))])
+        );
+    }
+
+    #[test]
+    fn query_history_filters_and_sorts() {
+        let root = write_history(&[
+            r#"{"truncated_before":5}"#,
+            r#"{"time":30,"kind":"updated","id":"3-300","old_id":"2-200","path":"docs/b.txt"}"#,
+            r#"{"time":10,"kind":"added","id":"1-100","old_id":null,"path":"a.txt"}"#,
+            "not json",
+            r#"{"time":20,"kind":"added","id":"2-200","old_id":null,"path":"docs/b.txt"}"#,
+        ]);
+        let root = root.path();
+
+        let times = |records: Vec<HistoryRecord>| {
+            records
+                .iter()
+                .map(|record| record.time)
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            times(query_history(root, &None, &None, None).unwrap()),
+            vec![10, 20, 30]
+        );
+        assert_eq!(
+            times(
+                query_history(root, &Some("docs/*".to_owned()), &None, None)
+                    .unwrap()
+            ),
+            vec![20, 30]
+        );
+        // Matches the previous id of updates too
+        assert_eq!(
+            times(
+                query_history(root, &None, &Some(id("2-200")), None).unwrap()
+            ),
+            vec![20, 30]
+        );
+        assert_eq!(
+            times(query_history(root, &None, &None, Some(20)).unwrap()),
+            vec![20, 30]
+        );
+
+        assert_eq!(read_history(root).unwrap().truncated_before, Some(5));
+    }
+
+    #[test]
+    fn query_history_without_history_is_empty() {
+        let root = tempfile::tempdir().unwrap();
+        assert!(query_history(root.path(), &None, &None, None)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn query_history_rejects_invalid_glob() {
+        let root = write_history(&[]);
+        assert!(matches!(
+            query_history(root.path(), &Some("[".to_owned()), &None, None),
+            Err(AppError::IndexError(_))
+        ));
+    }
+}
@@ -0,0 +1,209 @@
+use std::fs::metadata;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use arklib::id::ResourceId;
+use arklib::index::ResourceIndex;
+use walkdir::WalkDir;
+
+use crate::error::AppError;
+
+pub const MIN_SHORT_ID_LEN: usize = 4;
+
+/// Shortest prefix length which keeps all the ids unambiguous,
+/// but not shorter than `floor`. Ids shorter than that are shown in full.
+pub fn short_id_len(ids: &[ResourceId], floor: usize) -> usize {
+    let mut ids = ids
+        .iter()
+        .map(|id| id.to_string())
+        .collect::<Vec<_>>();
+    ids.sort();
+    ids.dedup();
+
+    // Sorted neighbours share the longest prefixes
+    let needed = ids
+        .windows(2)
+        .map(|pair| common_prefix_len(&pair[0], &pair[1]) + 1)
+        .max()
+        .unwrap_or(1);
+
+    needed.max(floor)
+}
+
+fn common_prefix_len(a: &str, b: &str) -> usize {
+    a.chars()
+        .zip(b.chars())
+        .take_while(|(a, b)| a == b)
+        .count()
+}
+
+pub fn shorten(id: &ResourceId, len: usize) -> String {
+    id.to_string().chars().take(len).collect()
+}
+
+/// Parses a full id, telling which part of it is wrong
+pub fn parse_id(id: &str) -> Result<ResourceId, AppError> {
+    ResourceId::from_str(id).map_err(|e| {
+        let reason = match id.split_once('-') {
+            None => {
+                "the '-' between the size and the hash is missing".to_owned()
+            }
+            Some(("", _)) => "the size before '-' is missing".to_owned(),
+            Some((_, "")) => "the hash after '-' is missing".to_owned(),
+            Some(_) => e.to_string(),
+        };
+        AppError::InvalidId(format!("'{}', {}", id, reason))
+    })
+}
+
+pub fn expand_id(
+    ids: &[ResourceId],
+    prefix: &str,
+) -> Result<ResourceId, AppError> {
+    let mut candidates = ids
+        .iter()
+        .filter(|id| id.to_string().starts_with(prefix))
+        .collect::<Vec<_>>();
+    candidates.sort_by_key(|id| id.to_string());
+    candidates.dedup();
+
+    if let Some(exact) = candidates
+        .iter()
+        .find(|id| id.to_string() == prefix)
+    {
+        return Ok(**exact);
+    }
+
+    match candidates.as_slice() {
+        [] => Err(AppError::InvalidIdPrefix(format!(
+            "no resource id starts with {}",
+            prefix
+        ))),
+        [id] => Ok(**id),
+        _ => Err(AppError::InvalidIdPrefix(format!(
+            "{} is ambiguous, candidates are: {}",
+            prefix,
+            candidates
+                .iter()
+                .map(|id| id.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ))),
+    }
+}
+
+/// Ids the files would get in the index, files are hashed as streams.
+/// Directories are walked only when `recursive` is set.
+pub fn compute_ids(
+    paths: &[PathBuf],
+    recursive: bool,
+) -> Result<Vec<(ResourceId, PathBuf)>, AppError> {
+    let mut ids = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            ids.push((compute_id(path)?, path.clone()));
+            continue;
+        }
+        if !recursive {
+            return Err(AppError::InvalidOptions(format!(
+                "{} is a directory, pass --recursive to hash its files",
+                path.display()
+            )));
+        }
+
+        for entry in WalkDir::new(path).sort_by_file_name() {
+            let entry = entry
+                .map_err(|e| AppError::FileOperationError(e.to_string()))?;
+            if entry.file_type().is_file() {
+                ids.push((compute_id(entry.path())?, entry.into_path()));
+            }
+        }
+    }
+
+    Ok(ids)
+}
+
+pub fn compute_id(path: &Path) -> Result<ResourceId, AppError> {
+    let size = metadata(path)?.len();
+    Ok(ResourceId::compute(size, path)?)
+}
+
+/// Paths currently indexed under the id, several in case of collisions
+pub fn find_paths(index: &ResourceIndex, id: ResourceId) -> Vec<PathBuf> {
+    let mut paths = index
+        .path2id
+        .iter()
+        .filter(|(_, entry)| entry.id == id)
+        .map(|(path, _)| path.to_owned().into_path_buf())
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ids(ids: &[&str]) -> Vec<ResourceId> {
+        ids.iter().map(|id| id.parse().unwrap()).collect()
+    }
+
+    fn assert_round_trip(ids: &[ResourceId], len: usize) {
+        for id in ids {
+            assert_eq!(expand_id(ids, &shorten(id, len)).unwrap(), *id);
+        }
+    }
+
+    #[test]
+    fn short_id_len_covers_shared_prefixes() {
+        let cases: &[(&[&str], usize, usize)] = &[
+            (&[], MIN_SHORT_ID_LEN, MIN_SHORT_ID_LEN),
+            (&["100-1"], 1, 1),
+            (&["100-1", "200-2"], 1, 1),
+            (&["100-1", "200-2"], MIN_SHORT_ID_LEN, MIN_SHORT_ID_LEN),
+            (&["123456-1", "123456-2"], 1, 8),
+            (&["1234567890-111111", "1234567890-111112"], 1, 17),
+            // Duplicates don't make the ids ambiguous
+            (&["100-1", "100-1", "200-2"], 1, 1),
+            // One id is a prefix of the other
+            (&["10-1", "10-12"], 1, 5),
+        ];
+
+        for (case, floor, expected) in cases {
+            let ids = ids(case);
+            let len = short_id_len(&ids, *floor);
+            assert_eq!(len, *expected, "{:?}", case);
+            assert_round_trip(&ids, len);
+        }
+    }
+
+    #[test]
+    fn expand_id_rejects_unknown_prefix() {
+        let ids = ids(&["100-1", "200-2"]);
+        assert!(matches!(
+            expand_id(&ids, "3"),
+            Err(AppError::InvalidIdPrefix(_))
+        ));
+    }
+
+    #[test]
+    fn expand_id_becomes_ambiguous_after_adding_an_entry() {
+        let mut ids = ids(&["100-1", "200-2"]);
+        let len = short_id_len(&ids, 1);
+        let short = shorten(&ids[0], len);
+        assert_eq!(expand_id(&ids, &short).unwrap(), ids[0]);
+
+        ids.push("150-3".parse().unwrap());
+        match expand_id(&ids, &short) {
+            Err(AppError::InvalidIdPrefix(message)) => {
+                assert!(message.contains("100-1"), "{}", message);
+                assert!(message.contains("150-3"), "{}", message);
+            }
+            other => panic!("expected an ambiguous prefix, got {:?}", other),
+        }
+
+        let len = short_id_len(&ids, 1);
+        assert_eq!(len, 2);
+        assert_round_trip(&ids, len);
+    }
+}
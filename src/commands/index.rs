@@ -0,0 +1,217 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{create_dir_all, rename, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use arklib::id::ResourceId;
+use arklib::index::ResourceIndex;
+use arklib::{ARK_FOLDER, INDEX_PATH};
+use walkdir::WalkDir;
+
+use crate::commands::id::compute_id;
+use crate::error::AppError;
+use crate::models::kind::Kind;
+use crate::util::{
+    index_events, index_snapshot, progress_bar, provide_index, IndexEvent,
+};
+
+/// Scans the whole root ignoring the stored index and overwrites it.
+/// Returns the amount of indexed resources and the time it took.
+/// The stored index is replaced only once the new one is written,
+/// so interrupting the rebuild leaves it intact.
+pub fn rebuild_index(root: &PathBuf) -> Result<(usize, Duration), AppError> {
+    let start = Instant::now();
+    let progress = progress_bar(&format!("Indexing {}", root.display()), None);
+    let index = ResourceIndex::build(root);
+    progress.finish_and_clear();
+
+    store_index(&index, root)?;
+
+    Ok((index.size(), start.elapsed()))
+}
+
+/// Writes the index in the format of `ResourceIndex::store`
+/// into a sibling temporary file and renames it over the index,
+/// so that the index on disk is always complete
+fn store_index(index: &ResourceIndex, root: &Path) -> Result<(), AppError> {
+    let root = root.canonicalize()?;
+    let index_path = root.join(ARK_FOLDER).join(INDEX_PATH);
+    let tmp_path = index_path.with_extension("tmp");
+
+    let mut entries = index
+        .path2id
+        .iter()
+        .map(|(path, entry)| {
+            let millis = entry
+                .modified
+                .duration_since(UNIX_EPOCH)
+                .map_err(|e| AppError::IndexError(e.to_string()))?
+                .as_millis();
+            let path = path.as_path();
+            let path = path.strip_prefix(&root).unwrap_or(path);
+            Ok(format!("{} {} {}\n", millis, entry.id, path.display()))
+        })
+        .collect::<Result<Vec<_>, AppError>>()?;
+    entries.sort();
+
+    create_dir_all(root.join(ARK_FOLDER))?;
+    let mut file = File::create(&tmp_path)?;
+    file.write_all(entries.concat().as_bytes())?;
+    file.sync_all()?;
+    rename(&tmp_path, &index_path)?;
+
+    Ok(())
+}
+
+/// Applies the changes of the root to the stored index,
+/// or builds it if there is none yet.
+/// Returns the changed paths and the time it took.
+pub fn update_index(
+    root: &PathBuf,
+) -> Result<(Vec<IndexEvent>, Duration), AppError> {
+    let start = Instant::now();
+    let progress = progress_bar(&format!("Indexing {}", root.display()), None);
+    let (before, index) = match ResourceIndex::load(root) {
+        Ok(mut index) => {
+            let before = index_snapshot(&index);
+            index
+                .update_all()
+                .map_err(|e| AppError::IndexError(e.to_string()))?;
+            (before, index)
+        }
+        Err(_) => (HashMap::new(), ResourceIndex::build(root)),
+    };
+    progress.finish_and_clear();
+    index
+        .store()
+        .map_err(|e| AppError::IndexError(e.to_string()))?;
+
+    let events = index_events(&before, &index_snapshot(&index));
+    Ok((events, start.elapsed()))
+}
+
+pub struct IndexStats {
+    pub resources: usize,
+    pub paths: usize,
+    /// Ids shared by several paths
+    pub collisions: usize,
+    /// Sum of the sizes of all indexed paths
+    pub bytes: u64,
+    /// Amount of paths and their total size per kind
+    pub kinds: BTreeMap<String, (usize, u64)>,
+    /// Biggest paths first
+    pub largest: Vec<(PathBuf, u64)>,
+    pub oldest: Option<SystemTime>,
+    pub newest: Option<SystemTime>,
+}
+
+/// Sizes are taken from the ids, so no file is read or hashed again
+pub fn index_stats(root: &PathBuf, top: usize) -> IndexStats {
+    let index = provide_index(root);
+    let modified = index
+        .path2id
+        .values()
+        .map(|entry| entry.modified)
+        .collect::<Vec<_>>();
+
+    let mut kinds: BTreeMap<String, (usize, u64)> = BTreeMap::new();
+    let mut sizes = Vec::with_capacity(index.path2id.len());
+    for (path, entry) in index.path2id.iter() {
+        let size = entry.id.data_size;
+        let kind = kinds
+            .entry(Kind::of(path.as_path()).to_string())
+            .or_default();
+        kind.0 += 1;
+        kind.1 += size;
+        sizes.push((path.to_path_buf(), size));
+    }
+    sizes.sort_by(|(a_path, a_size), (b_path, b_size)| {
+        b_size
+            .cmp(a_size)
+            .then_with(|| a_path.cmp(b_path))
+    });
+    let bytes = sizes.iter().map(|(_, size)| size).sum();
+    sizes.truncate(top);
+
+    IndexStats {
+        resources: index.id2path.len(),
+        paths: index.path2id.len(),
+        collisions: index.collisions.len(),
+        bytes,
+        kinds,
+        largest: sizes,
+        oldest: modified.iter().min().copied(),
+        newest: modified.iter().max().copied(),
+    }
+}
+
+#[derive(Default)]
+pub struct VerifyReport {
+    /// Indexed paths which are gone
+    pub missing: Vec<PathBuf>,
+    /// Files of the root which are not indexed
+    pub unindexed: Vec<PathBuf>,
+    /// Paths with their indexed and current ids
+    pub modified: Vec<(PathBuf, ResourceId, ResourceId)>,
+}
+
+impl VerifyReport {
+    pub fn is_consistent(&self) -> bool {
+        self.missing.is_empty()
+            && self.unindexed.is_empty()
+            && self.modified.is_empty()
+    }
+}
+
+/// Hashes every file of the root again and compares it with the stored
+/// index, which is read as is without being updated
+pub fn verify_index(root: &PathBuf) -> Result<VerifyReport, AppError> {
+    let index = ResourceIndex::load(root)
+        .map_err(|e| AppError::IndexError(e.to_string()))?;
+    let mut report = VerifyReport::default();
+
+    for (path, entry) in index.path2id.iter() {
+        let path = path.as_path();
+        if !path.is_file() {
+            report.missing.push(path.to_path_buf());
+            continue;
+        }
+
+        let current = compute_id(path)?;
+        if current != entry.id {
+            report
+                .modified
+                .push((path.to_path_buf(), entry.id, current));
+        }
+    }
+
+    let indexed = index
+        .path2id
+        .keys()
+        .map(|path| path.as_path().to_path_buf())
+        .collect::<HashSet<_>>();
+    // Hidden files and the `.ark` folder are never indexed
+    let files = WalkDir::new(root.canonicalize()?)
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() == 0
+                || !entry
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with('.')
+        })
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_file());
+    for file in files {
+        if !indexed.contains(file.path()) {
+            report.unindexed.push(file.into_path());
+        }
+    }
+
+    report.missing.sort();
+    report.unindexed.sort();
+    report.modified.sort();
+
+    Ok(report)
+}
@@ -1,22 +1,148 @@
-use arklib::{id::ResourceId, link::Link};
+use arklib::{
+    id::ResourceId,
+    link::{Link, OpenGraph},
+    modify, AtomicFile, ARK_FOLDER, METADATA_STORAGE_FOLDER,
+    PREVIEWS_STORAGE_FOLDER,
+};
+use std::fs::{remove_dir_all, remove_file};
 use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
 use url::Url;
 
+use crate::commands::storage::open_storage;
 use crate::error::AppError;
-use crate::util::provide_index; // Import your custom AppError type
+use crate::models::format::Format;
+use crate::models::storage::{Storage, StorageType};
+use crate::util::{provide_index, read_storage_value, translate_storage};
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Storages with values set by the user, which follow the link
+/// to its new id when the url changes
+const USER_STORAGES: [&str; 3] = ["tags", "scores", "properties"];
 
 pub async fn create_link(
     root: &PathBuf,
     url: &str,
-    title: &str,
+    title: Option<String>,
     desc: Option<String>,
+    offline: bool,
 ) -> Result<(), AppError> {
     let url = Url::parse(url)
         .map_err(|_| AppError::LinkCreationError("Invalid url".to_owned()))?;
-    let link: Link = Link::new(url, title.to_owned(), desc.to_owned());
-    link.save(root, true)
+
+    let (title, desc, graph) = match title {
+        Some(title) => (title, desc, None),
+        None if offline => {
+            return Err(AppError::LinkCreationError(
+                "Title must be provided in offline mode".to_owned(),
+            ))
+        }
+        None => {
+            let graph = fetch_preview(&url).await?;
+            let title = graph.title.ok_or_else(|| {
+                AppError::LinkCreationError(
+                    "Page has no title, provide it explicitly".to_owned(),
+                )
+            })?;
+            let desc = desc.or(graph.description);
+
+            println!("Fetched title: {}", title);
+            if let Some(desc) = &desc {
+                println!("Fetched description: {}", desc);
+            }
+
+            (title, desc, Some(graph))
+        }
+    };
+
+    let link: Link = Link::new(url, title, desc);
+    if offline {
+        return link
+            .save(root, false)
+            .await
+            .map_err(|e| AppError::LinkCreationError(e.to_string()));
+    }
+
+    match graph {
+        // The page was fetched for its title already
+        Some(graph) => {
+            link.save(root, false)
+                .await
+                .map_err(|e| AppError::LinkCreationError(e.to_string()))?;
+            store_preview(root, &link, &graph).await
+        }
+        None => tokio::time::timeout(FETCH_TIMEOUT, link.save(root, true))
+            .await
+            .map_err(|_| fetch_timeout(&link.url))?
+            .map_err(|e| AppError::LinkCreationError(e.to_string())),
+    }
+}
+
+/// Stores the metadata and the preview image of the link the way
+/// `Link::save` does, from a page fetched before
+async fn store_preview(
+    root: &PathBuf,
+    link: &Link,
+    graph: &OpenGraph,
+) -> Result<(), AppError> {
+    let id = link.id()?;
+    let ark_folder = root.join(ARK_FOLDER);
+
+    let metadata = serde_json::to_string(graph)
+        .map_err(|e| AppError::LinkCreationError(e.to_string()))?;
+    Storage::new(
+        ark_folder.join(METADATA_STORAGE_FOLDER),
+        StorageType::Folder,
+    )?
+    .insert(id, &metadata, Format::Raw)?;
+
+    // The link is saved already, so a missing image is not an error
+    match tokio::time::timeout(FETCH_TIMEOUT, graph.fetch_image()).await {
+        Ok(Some(image)) => {
+            let preview = AtomicFile::new(
+                ark_folder
+                    .join(PREVIEWS_STORAGE_FOLDER)
+                    .join(id.to_string()),
+            )?;
+            modify(&preview, |_| image.clone())?;
+        }
+        Ok(None) => {}
+        Err(_) => eprintln!(
+            "Fetching the preview image of {} timed out, skipped",
+            link.url
+        ),
+    }
+
+    Ok(())
+}
+
+async fn fetch_preview(url: &Url) -> Result<OpenGraph, AppError> {
+    let link = Link::new(url.clone(), String::new(), None);
+
+    tokio::time::timeout(FETCH_TIMEOUT, link.get_preview())
         .await
-        .map_err(|e| AppError::LinkCreationError(e.to_string()))
+        .map_err(|_| {
+            AppError::NetworkError(format!(
+                "Fetching {} timed out, provide the title explicitly",
+                url
+            ))
+        })?
+        .map_err(|e| {
+            AppError::NetworkError(format!(
+                "Could not fetch {}: {}, provide the title explicitly",
+                url, e
+            ))
+        })
+}
+
+fn fetch_timeout(url: &Url) -> AppError {
+    AppError::NetworkError(format!(
+        "Fetching {} timed out after {} seconds",
+        url,
+        FETCH_TIMEOUT.as_secs()
+    ))
 }
 
 pub fn load_link(
@@ -24,10 +150,21 @@ pub fn load_link(
     file_path: &Option<PathBuf>,
     id: &Option<ResourceId>,
 ) -> Result<Link, AppError> {
-    let path_from_index = id.map(|id| {
-        let index = provide_index(root);
-        index.id2path[&id].as_path().to_path_buf()
-    });
+    let path_from_index = id
+        .map(|id| {
+            let index = provide_index(root);
+            index
+                .id2path
+                .get(&id)
+                .map(|path| path.as_path().to_path_buf())
+                .ok_or_else(|| {
+                    AppError::LinkLoadError(format!(
+                        "Id {} was not found in the index",
+                        id
+                    ))
+                })
+        })
+        .transpose()?;
     let path_from_user = file_path;
 
     let path = match (path_from_user, path_from_index) {
@@ -52,3 +189,220 @@ pub fn load_link(
 
     Ok(arklib::link::Link::load(root, &path)?)
 }
+
+pub fn list_links(
+    root: &PathBuf,
+    filter_tag: &Option<String>,
+) -> Vec<(ResourceId, Link)> {
+    let index = provide_index(root);
+
+    let mut links = index
+        .path2id
+        .iter()
+        .filter(|(path, _)| {
+            path.as_path()
+                .extension()
+                .map(|ext| ext == "link")
+                .unwrap_or(false)
+        })
+        .filter(|(_, entry)| match filter_tag {
+            Some(tag) => {
+                read_storage_value(root, "tags", &entry.id.to_string(), &None)
+                    .map(|tags| tags.split(',').any(|t| t.trim() == tag))
+                    .unwrap_or(false)
+            }
+            None => true,
+        })
+        .filter_map(|(path, entry)| {
+            match Link::load(root, &path.as_path().to_path_buf()) {
+                Ok(link) => Some((entry.id, link)),
+                Err(e) => {
+                    eprintln!(
+                        "Could not load link {}: {}",
+                        path.as_path().display(),
+                        e
+                    );
+                    None
+                }
+            }
+        })
+        .collect::<Vec<_>>();
+
+    links.sort_by(|(_, a), (_, b)| a.url.as_str().cmp(b.url.as_str()));
+    links
+}
+
+pub fn open_link(
+    root: &PathBuf,
+    id: ResourceId,
+    print_only: bool,
+) -> Result<(), AppError> {
+    let path = provide_index(root)
+        .id2path
+        .get(&id)
+        .map(|path| path.as_path().to_path_buf())
+        .ok_or(AppError::ResourceNotFound(id))?;
+    // Failures to load an actual link are reported as they are
+    if path.extension().map_or(true, |ext| ext != "link") {
+        return Err(AppError::LinkOpenError(format!(
+            "Id {} is not a link resource",
+            id
+        )));
+    }
+    let link = load_link(root, &Some(path), &None)?;
+
+    if print_only {
+        println!("{}", link.url);
+        return Ok(());
+    }
+
+    let mut command = if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else if cfg!(target_os = "windows") {
+        let mut command = Command::new("rundll32");
+        command.arg("url.dll,FileProtocolHandler");
+        command
+    } else {
+        Command::new("xdg-open")
+    };
+
+    let status = command
+        .arg(link.url.as_str())
+        .status()
+        .map_err(|e| AppError::LinkOpenError(e.to_string()))?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(AppError::LinkOpenError(format!(
+            "Browser exited with {}",
+            status
+        )))
+    }
+}
+
+fn link_path(root: &PathBuf, id: ResourceId) -> Result<PathBuf, AppError> {
+    let index = provide_index(root);
+    let path = index
+        .id2path
+        .get(&id)
+        .map(|path| path.as_path().to_path_buf())
+        .ok_or_else(|| {
+            AppError::LinkLoadError(format!(
+                "Id {} was not found in the index",
+                id
+            ))
+        })?;
+
+    match path.extension() {
+        Some(ext) if ext == "link" => Ok(path),
+        _ => Err(AppError::LinkLoadError(format!(
+            "Id {} is not a link resource",
+            id
+        ))),
+    }
+}
+
+// Storages never written to are not created just to be looked into
+fn existing_storage(
+    root: &PathBuf,
+    name: &str,
+) -> Result<Option<Storage>, AppError> {
+    match translate_storage(&Some(root.to_owned()), name) {
+        Some((path, _)) if path.exists() => {
+            Ok(Some(open_storage(root, name, &None)?))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Removes the link together with its values in the user storages
+/// and its cached metadata and preview
+pub fn delete_link(root: &PathBuf, id: ResourceId) -> Result<(), AppError> {
+    let path = link_path(root, id)?;
+    remove_file(path)?;
+
+    for name in USER_STORAGES {
+        if let Some(mut storage) = existing_storage(root, name)? {
+            let mut batch = storage.begin_batch();
+            batch.remove(id)?;
+            batch.commit()?;
+        }
+    }
+
+    let ark_folder = root.join(ARK_FOLDER);
+    for storage in [METADATA_STORAGE_FOLDER, PREVIEWS_STORAGE_FOLDER] {
+        let entry = ark_folder.join(storage).join(id.to_string());
+        if entry.exists() {
+            remove_dir_all(entry)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Copies the user values of the old id to the new one, the properties
+// saved with the updated link take precedence over the old ones
+fn migrate_values(
+    root: &PathBuf,
+    old_id: ResourceId,
+    new_id: ResourceId,
+) -> Result<(), AppError> {
+    for name in USER_STORAGES {
+        let mut storage = match existing_storage(root, name)? {
+            Some(storage) => storage,
+            None => continue,
+        };
+
+        let value = match storage.read(old_id) {
+            Ok(value) => value,
+            Err(AppError::ResourceNotFound(_)) => continue,
+            Err(e) => return Err(e),
+        };
+        match storage.read(new_id) {
+            Ok(_) if name == "properties" => continue,
+            Ok(_) | Err(AppError::ResourceNotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+        storage.insert(new_id, &value, Format::Raw)?;
+    }
+
+    Ok(())
+}
+
+/// Returns the id of the updated link,
+/// which differs from the original one if the url was changed
+pub async fn update_link(
+    root: &PathBuf,
+    id: ResourceId,
+    url: Option<String>,
+    title: Option<String>,
+    desc: Option<String>,
+) -> Result<ResourceId, AppError> {
+    let path = link_path(root, id)?;
+    let link = Link::load(root, &path)?;
+
+    let url = match url {
+        Some(url) => Url::parse(&url).map_err(|_| {
+            AppError::LinkCreationError("Invalid url".to_owned())
+        })?,
+        None => link.url,
+    };
+    let title = title.unwrap_or(link.prop.title);
+    let desc = desc.or(link.prop.desc);
+
+    let updated = Link::new(url, title, desc);
+    let new_id = updated.id()?;
+
+    updated
+        .save(root, false)
+        .await
+        .map_err(|e| AppError::LinkCreationError(e.to_string()))?;
+
+    if new_id != id {
+        migrate_values(root, id, new_id)?;
+        delete_link(root, id)?;
+    }
+
+    Ok(new_id)
+}
@@ -0,0 +1,37 @@
+use std::fs::{create_dir_all, File};
+use std::io::Write;
+use std::path::Path;
+
+use clap::App;
+use clap_mangen::Man;
+
+use crate::error::AppError;
+
+pub fn render_page<W: Write>(app: App, output: &mut W) -> Result<(), AppError> {
+    Ok(Man::new(app).render(output)?)
+}
+
+/// Writes a page for the command and every nested subcommand into `dir`,
+/// named like `ark-cli-storage-list.1`. Returns the written files count.
+pub fn write_pages(app: App, dir: &Path) -> Result<usize, AppError> {
+    create_dir_all(dir)?;
+
+    let name = app.get_name().to_owned();
+    let mut file = File::create(dir.join(format!("{}.1", name)))?;
+    render_page(app.clone(), &mut file)?;
+
+    let mut written = 1;
+    for subcommand in app.get_subcommands() {
+        if subcommand.get_name() == "help" {
+            continue;
+        }
+        let subcommand = subcommand.clone().name(format!(
+            "{}-{}",
+            name,
+            subcommand.get_name()
+        ));
+        written += write_pages(subcommand, dir)?;
+    }
+
+    Ok(written)
+}
@@ -0,0 +1,289 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::{copy, BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::error::AppError;
+use crate::models::manifest::ManifestFormat;
+use crate::util::provide_index;
+
+#[derive(Default)]
+pub struct ManifestReport {
+    pub matched: Vec<PathBuf>,
+    /// Path, digest from the manifest and the actual one
+    pub mismatched: Vec<(PathBuf, String, String)>,
+    /// Listed in the manifest, but absent in the root
+    pub missing: Vec<PathBuf>,
+    /// Indexed in the root, but absent in the manifest
+    pub unlisted: Vec<PathBuf>,
+}
+
+/// Reads `(path, sha256)` pairs, paths are kept as written in the manifest
+pub fn parse_manifest(
+    manifest: &Path,
+    format: ManifestFormat,
+) -> Result<Vec<(PathBuf, String)>, AppError> {
+    let reader = BufReader::new(File::open(manifest)?);
+
+    let mut entries = Vec::new();
+    // hashdeep declares its columns in a header
+    let mut columns: Option<Vec<String>> = None;
+
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let entry = match format {
+            ManifestFormat::Sha256sum => {
+                line.split_once(' ').map(|(digest, path)| {
+                    // The separator is followed by ` ` or by `*` in binary mode
+                    let path = path
+                        .strip_prefix(' ')
+                        .or_else(|| path.strip_prefix('*'))
+                        .unwrap_or(path);
+                    (PathBuf::from(path), digest.to_lowercase())
+                })
+            }
+            ManifestFormat::GitAnnex => {
+                line.split_once(' ').and_then(|(key, path)| {
+                    Some((PathBuf::from(path), annex_digest(key)?))
+                })
+            }
+            ManifestFormat::Hashdeep => {
+                if let Some(header) = line.strip_prefix("%%%% ") {
+                    if header.contains(',') {
+                        columns = Some(
+                            header.split(',').map(|c| c.to_owned()).collect(),
+                        );
+                    }
+                    continue;
+                }
+                if line.starts_with('#') {
+                    continue;
+                }
+
+                let columns = columns.as_ref().ok_or_else(|| {
+                    AppError::ManifestError(
+                        "hashdeep header is missing".to_owned(),
+                    )
+                })?;
+                let sha256 = columns
+                    .iter()
+                    .position(|c| c == "sha256")
+                    .ok_or_else(|| {
+                        AppError::ManifestError(
+                            "hashdeep manifest has no sha256 column".to_owned(),
+                        )
+                    })?;
+
+                // The file name is the last column and may contain commas
+                let values = line
+                    .splitn(columns.len(), ',')
+                    .collect::<Vec<_>>();
+                if values.len() == columns.len() {
+                    Some((
+                        PathBuf::from(values[columns.len() - 1]),
+                        values[sha256].to_lowercase(),
+                    ))
+                } else {
+                    None
+                }
+            }
+        };
+
+        match entry {
+            Some(entry) => entries.push(entry),
+            None => {
+                return Err(AppError::ManifestError(format!(
+                    "Invalid line {}: {}",
+                    i + 1,
+                    line
+                )))
+            }
+        }
+    }
+
+    Ok(entries)
+}
+
+/// git-annex keys look like `SHA256E-s1024--<digest>.jpg`
+fn annex_digest(key: &str) -> Option<String> {
+    let (backend, rest) = key.split_once('-')?;
+    if backend != "SHA256" && backend != "SHA256E" {
+        return None;
+    }
+
+    let (_, digest) = rest.split_once("--")?;
+    let digest = digest.split('.').next()?;
+
+    Some(digest.to_lowercase())
+}
+
+pub fn sha256(path: &Path) -> Result<String, AppError> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    copy(&mut file, &mut hasher)?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+pub fn verify_manifest(
+    root: &PathBuf,
+    entries: Vec<(PathBuf, String)>,
+) -> Result<ManifestReport, AppError> {
+    let index = provide_index(root);
+    let mut report = ManifestReport::default();
+
+    let mut listed = HashSet::new();
+    for (path, expected) in entries {
+        let full_path = if path.is_absolute() {
+            path.clone()
+        } else {
+            root.join(&path)
+        };
+
+        let canonical = match full_path.canonicalize() {
+            Ok(canonical) if canonical.is_file() => canonical,
+            _ => {
+                report.missing.push(path);
+                continue;
+            }
+        };
+
+        let actual = sha256(&canonical)?;
+        if actual == expected {
+            report.matched.push(path);
+        } else {
+            report.mismatched.push((path, expected, actual));
+        }
+
+        listed.insert(canonical);
+    }
+
+    report.unlisted = index
+        .path2id
+        .keys()
+        .map(|path| path.as_path().to_path_buf())
+        .filter(|path| !listed.contains(path))
+        .collect();
+    report.unlisted.sort();
+
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIGEST: &str =
+        "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+    fn parse(
+        format: ManifestFormat,
+        content: &str,
+    ) -> Result<Vec<(PathBuf, String)>, AppError> {
+        let dir = tempfile::TempDir::new().unwrap();
+        let manifest = dir.path().join("manifest");
+        std::fs::write(&manifest, content).unwrap();
+        parse_manifest(&manifest, format)
+    }
+
+    #[test]
+    fn parse_manifest_formats() {
+        let upper = DIGEST.to_uppercase();
+        let hashdeep_header = "%%%% HASHDEEP-1.0\n\
+             %%%% size,md5,sha256,filename\n\
+             ## Invoked from: /root\n";
+        let cases = [
+            (
+                ManifestFormat::Sha256sum,
+                format!("{} *bin/tool\n", DIGEST),
+                "bin/tool",
+            ),
+            (
+                ManifestFormat::Sha256sum,
+                format!("{}  text file.txt\n", DIGEST),
+                "text file.txt",
+            ),
+            (
+                ManifestFormat::Sha256sum,
+                format!("\n{}  a.txt\n\n", upper),
+                "a.txt",
+            ),
+            (
+                ManifestFormat::GitAnnex,
+                format!("SHA256E-s1024--{}.jpg photos/cat.jpg\n", DIGEST),
+                "photos/cat.jpg",
+            ),
+            (
+                ManifestFormat::GitAnnex,
+                format!("SHA256-s0--{} empty\n", upper),
+                "empty",
+            ),
+            (
+                ManifestFormat::Hashdeep,
+                format!("{}0,d41d8cd9,{},./a.txt\n", hashdeep_header, DIGEST),
+                "./a.txt",
+            ),
+            (
+                ManifestFormat::Hashdeep,
+                format!(
+                    "{}0,d41d8cd9,{},./one, two.txt\n",
+                    hashdeep_header, upper
+                ),
+                "./one, two.txt",
+            ),
+        ];
+
+        for (format, content, path) in cases {
+            assert_eq!(
+                parse(format, &content).unwrap(),
+                vec![(PathBuf::from(path), DIGEST.to_owned())],
+                "{:?}: {}",
+                format,
+                content
+            );
+        }
+    }
+
+    #[test]
+    fn parse_manifest_rejects_invalid_lines() {
+        let cases = [
+            (ManifestFormat::Sha256sum, format!("{}\n", DIGEST)),
+            (
+                ManifestFormat::GitAnnex,
+                "MD5E-s0--d41d8cd98f00b204e9800998ecf8427e.txt a.txt\n"
+                    .to_owned(),
+            ),
+            (
+                ManifestFormat::GitAnnex,
+                format!("SHA256-{} a.txt\n", DIGEST),
+            ),
+            (ManifestFormat::Hashdeep, format!("0,{},a.txt\n", DIGEST)),
+            (
+                ManifestFormat::Hashdeep,
+                "%%%% size,md5,filename\n0,d41d8cd9,a.txt\n".to_owned(),
+            ),
+            (
+                ManifestFormat::Hashdeep,
+                format!("%%%% size,sha256,filename\n{},a.txt\n", DIGEST),
+            ),
+        ];
+
+        for (format, content) in cases {
+            assert!(
+                matches!(
+                    parse(format, &content),
+                    Err(AppError::ManifestError(_))
+                ),
+                "{:?}: {}",
+                format,
+                content
+            );
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use arklib::id::ResourceId;
+use exif::{In, Tag};
+use lofty::{Accessor, TaggedFileExt};
+use serde_json::{Map, Value};
+
+use crate::commands::storage::open_storage;
+use crate::error::AppError;
+use crate::models::format::Format;
+use crate::models::kind::Kind;
+use crate::util::provide_index;
+
+/// Embedded metadata of images (EXIF), PDFs and audio files as a JSON
+/// object, empty for other files
+pub fn extract_metadata(path: &Path) -> Result<Map<String, Value>, AppError> {
+    let is_pdf = path
+        .extension()
+        .map(|ext| ext.eq_ignore_ascii_case("pdf"))
+        .unwrap_or(false);
+
+    match Kind::of(path) {
+        Kind::Image => image_metadata(path),
+        Kind::Audio => audio_metadata(path),
+        Kind::Document if is_pdf => pdf_metadata(path),
+        _ => Ok(Map::new()),
+    }
+}
+
+fn image_metadata(path: &Path) -> Result<Map<String, Value>, AppError> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut metadata = Map::new();
+    let exif = match exif::Reader::new().read_from_container(&mut reader) {
+        Ok(exif) => exif,
+        // Images without EXIF, like most PNGs, have nothing to extract
+        Err(exif::Error::NotFound(_)) => return Ok(metadata),
+        Err(e) => return Err(AppError::MetadataError(e.to_string())),
+    };
+
+    let ascii = |tag: Tag| match exif.get_field(tag, In::PRIMARY) {
+        Some(exif::Field {
+            value: exif::Value::Ascii(values),
+            ..
+        }) => values
+            .first()
+            .map(|value| String::from_utf8_lossy(value).trim().to_owned())
+            .filter(|value| !value.is_empty()),
+        _ => None,
+    };
+
+    // RFC 3339 without offset, which EXIF doesn't store, sorts by time
+    let taken_at = ascii(Tag::DateTimeOriginal)
+        .or_else(|| ascii(Tag::DateTime))
+        .and_then(|value| exif::DateTime::from_ascii(value.as_bytes()).ok());
+    if let Some(at) = taken_at {
+        metadata.insert(
+            "taken_at".to_owned(),
+            format!(
+                "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+                at.year, at.month, at.day, at.hour, at.minute, at.second
+            )
+            .into(),
+        );
+    }
+
+    let camera = [ascii(Tag::Make), ascii(Tag::Model)]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+    if !camera.is_empty() {
+        metadata.insert("camera".to_owned(), camera.join(" ").into());
+    }
+
+    let coordinate = |tag: Tag, ref_tag: Tag, negative: &str| {
+        let degrees = match exif.get_field(tag, In::PRIMARY) {
+            Some(exif::Field {
+                value: exif::Value::Rational(parts),
+                ..
+            }) if parts.len() >= 3 => {
+                parts[0].to_f64()
+                    + parts[1].to_f64() / 60.0
+                    + parts[2].to_f64() / 3600.0
+            }
+            _ => return None,
+        };
+        match ascii(ref_tag) {
+            Some(reference) if reference == negative => Some(-degrees),
+            _ => Some(degrees),
+        }
+    };
+    let latitude = coordinate(Tag::GPSLatitude, Tag::GPSLatitudeRef, "S");
+    let longitude = coordinate(Tag::GPSLongitude, Tag::GPSLongitudeRef, "W");
+    if let (Some(latitude), Some(longitude)) = (latitude, longitude) {
+        metadata.insert("latitude".to_owned(), latitude.into());
+        metadata.insert("longitude".to_owned(), longitude.into());
+    }
+
+    Ok(metadata)
+}
+
+fn pdf_metadata(path: &Path) -> Result<Map<String, Value>, AppError> {
+    let document = lopdf::Document::load(path)
+        .map_err(|e| AppError::MetadataError(e.to_string()))?;
+    let mut metadata = Map::new();
+
+    let info = document
+        .trailer
+        .get(b"Info")
+        .and_then(|info| info.as_reference())
+        .and_then(|id| document.get_dictionary(id));
+    let info = match info {
+        Ok(info) => info,
+        // Documents without an info dictionary have nothing to extract
+        Err(_) => return Ok(metadata),
+    };
+
+    for (key, name) in [(&b"Title"[..], "title"), (b"Author", "author")] {
+        let text = info
+            .get(key)
+            .and_then(|value| value.as_str())
+            .map(pdf_text);
+        if let Ok(text) = text {
+            if !text.is_empty() {
+                metadata.insert(name.to_owned(), text.into());
+            }
+        }
+    }
+
+    Ok(metadata)
+}
+
+// PDF text strings are UTF-16 with a byte order mark or PDFDocEncoding,
+// which matches Latin-1 for printable characters
+fn pdf_text(bytes: &[u8]) -> String {
+    let text: String = match bytes {
+        [0xFE, 0xFF, rest @ ..] => {
+            let units = rest
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]))
+                .collect::<Vec<_>>();
+            String::from_utf16_lossy(&units)
+        }
+        _ => bytes.iter().map(|&byte| byte as char).collect(),
+    };
+    text.trim().to_owned()
+}
+
+fn audio_metadata(path: &Path) -> Result<Map<String, Value>, AppError> {
+    let file = lofty::read_from_path(path)
+        .map_err(|e| AppError::MetadataError(e.to_string()))?;
+    let mut metadata = Map::new();
+
+    let tag = match file.primary_tag().or_else(|| file.first_tag()) {
+        Some(tag) => tag,
+        None => return Ok(metadata),
+    };
+    if let Some(title) = tag.title() {
+        metadata.insert("title".to_owned(), title.to_string().into());
+    }
+    if let Some(artist) = tag.artist() {
+        metadata.insert("artist".to_owned(), artist.to_string().into());
+    }
+    if let Some(album) = tag.album() {
+        metadata.insert("album".to_owned(), album.to_string().into());
+    }
+    if let Some(year) = tag.year() {
+        metadata.insert("year".to_owned(), year.into());
+    }
+
+    Ok(metadata)
+}
+
+/// Extracts the metadata of the resource into the `metadata` storage.
+/// An existing entry is kept unless `force` is given,
+/// empty results are never stored.
+/// Returns the stored metadata and whether it was extracted now.
+pub fn store_metadata(
+    root: &PathBuf,
+    id: ResourceId,
+    force: bool,
+) -> Result<(Value, bool), AppError> {
+    let mut storage = open_storage(root, "metadata", &None)?;
+    if !force {
+        match storage.read(id) {
+            Ok(existing) => {
+                let existing = serde_json::from_str(&existing)
+                    .unwrap_or(Value::String(existing));
+                return Ok((existing, false));
+            }
+            Err(AppError::ResourceNotFound(_)) => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    let path = provide_index(root)
+        .id2path
+        .get(&id)
+        .map(|path| path.to_owned().into_path_buf())
+        .ok_or(AppError::ResourceNotFound(id))?;
+    let metadata = extract_metadata(&path)?;
+    // Nothing extracted must not replace what other apps stored,
+    // like the OpenGraph data of links
+    if metadata.is_empty() {
+        return match storage.read(id) {
+            Ok(existing) => Ok((
+                serde_json::from_str(&existing)
+                    .unwrap_or(Value::String(existing)),
+                false,
+            )),
+            Err(AppError::ResourceNotFound(_)) => {
+                Ok((Value::Object(metadata), false))
+            }
+            Err(e) => Err(e),
+        };
+    }
+
+    let metadata = Value::Object(metadata);
+    storage.insert(id, &metadata.to_string(), Format::Raw)?;
+
+    Ok((metadata, true))
+}
+
+#[derive(Default)]
+pub struct MetadataReport {
+    pub extracted: usize,
+    /// Resources with metadata already or of unsupported types
+    pub skipped: usize,
+    pub failed: usize,
+}
+
+/// Extracts the metadata of every indexed resource, failures are reported
+/// and skipped
+pub fn index_metadata(
+    root: &PathBuf,
+    force: bool,
+) -> Result<MetadataReport, AppError> {
+    let index = provide_index(root);
+    let mut storage = open_storage(root, "metadata", &None)?;
+    let existing = if force {
+        HashSet::new()
+    } else {
+        storage
+            .entries()?
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect::<HashSet<_>>()
+    };
+
+    let mut report = MetadataReport::default();
+    let mut batch = storage.begin_batch().auto_flush(Some(100), None);
+    for (id, path) in index.id2path.iter() {
+        if existing.contains(id) {
+            report.skipped += 1;
+            continue;
+        }
+
+        match extract_metadata(path.as_path()) {
+            Ok(metadata) if metadata.is_empty() => report.skipped += 1,
+            Ok(metadata) => {
+                batch.insert(*id, &Value::Object(metadata).to_string())?;
+                report.extracted += 1;
+            }
+            Err(e) => {
+                eprintln!(
+                    "Could not extract metadata of {}: {}",
+                    path.as_path().display(),
+                    e
+                );
+                report.failed += 1;
+            }
+        }
+    }
+    batch.commit()?;
+
+    Ok(report)
+}
+
+/// Value of `key` in the stored metadata, strings without quotes
+pub fn metadata_value(metadata: &str, key: &str) -> Option<String> {
+    match serde_json::from_str::<Value>(metadata)
+        .ok()?
+        .get(key)?
+    {
+        Value::Null => None,
+        Value::String(value) => Some(value.clone()),
+        value => Some(value.to_string()),
+    }
+}
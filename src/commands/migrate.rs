@@ -0,0 +1,316 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::UNIX_EPOCH;
+
+use arklib::id::ResourceId;
+use serde::{Deserialize, Serialize};
+
+use crate::commands::storage::open_storage;
+use crate::error::AppError;
+use crate::util::{index_snapshot, provide_index, translate_storage};
+
+/// Storages filled by the user, the rest is derived from the content
+/// and gets regenerated for the new ids anyway
+pub const USER_STORAGES: [&str; 3] = ["tags", "scores", "properties"];
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotEntry {
+    id: String,
+    /// Milliseconds since the epoch
+    modified: u64,
+}
+
+pub struct MigrationPlan {
+    /// Old and new ids of the paths which content changed in place
+    pub changed: Vec<(PathBuf, ResourceId, ResourceId)>,
+    /// Paths from the snapshot which are not in the index anymore
+    pub unmatched: Vec<PathBuf>,
+    /// Paths which id changed, but which failed the sanity checks,
+    /// with the reason
+    pub suspicious: Vec<(PathBuf, String)>,
+}
+
+fn modified_millis(path: &Path) -> Result<u64, AppError> {
+    let modified = std::fs::metadata(path)?.modified()?;
+    Ok(modified
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64)
+}
+
+/// Ids and modification times of the indexed paths, relative to the root
+pub fn take_snapshot(root: &PathBuf) -> Result<serde_json::Value, AppError> {
+    let canonical_root = root.canonicalize()?;
+    let index = provide_index(root);
+
+    let mut snapshot = BTreeMap::new();
+    for (path, id) in index_snapshot(&index) {
+        let entry = SnapshotEntry {
+            id: id.to_string(),
+            modified: modified_millis(&path)?,
+        };
+        let path = path
+            .strip_prefix(&canonical_root)
+            .unwrap_or(&path);
+        snapshot.insert(path.display().to_string(), entry);
+    }
+
+    serde_json::to_value(snapshot)
+        .map_err(|e| AppError::IndexError(e.to_string()))
+}
+
+/// Matches the snapshot against the current index by path.
+/// Paths which appeared after the snapshot are never matched.
+///
+/// A changed path is only migrated when its size is the one of the new id
+/// and it was modified after the snapshot, otherwise the index is outdated
+/// or the ids collide and the path is left for manual review.
+pub fn migration_plan(
+    root: &PathBuf,
+    snapshot: &Path,
+) -> Result<MigrationPlan, AppError> {
+    let snapshot: HashMap<PathBuf, SnapshotEntry> =
+        serde_json::from_reader(File::open(snapshot)?).map_err(|e| {
+            AppError::IndexError(format!("Invalid snapshot: {}", e))
+        })?;
+
+    let canonical_root = root.canonicalize()?;
+    let current = index_snapshot(&provide_index(root));
+
+    let mut changed = Vec::new();
+    let mut unmatched = Vec::new();
+    let mut suspicious = Vec::new();
+    for (path, entry) in snapshot {
+        let old_id = ResourceId::from_str(&entry.id)?;
+        let full_path = canonical_root.join(&path);
+        let new_id = match current.get(&full_path) {
+            None => {
+                unmatched.push(path);
+                continue;
+            }
+            Some(new_id) if *new_id == old_id => continue,
+            Some(new_id) => *new_id,
+        };
+
+        let size = std::fs::metadata(&full_path)?.len();
+        if size != new_id.data_size {
+            suspicious.push((
+                path,
+                format!(
+                    "the file has {} bytes, but its id {} is for {}, \
+                     update the index first",
+                    size, new_id, new_id.data_size
+                ),
+            ));
+        } else if modified_millis(&full_path)? <= entry.modified {
+            suspicious.push((
+                path,
+                "the id changed, but the file was not modified since \
+                 the snapshot"
+                    .to_owned(),
+            ));
+        } else {
+            changed.push((path, old_id, new_id));
+        }
+    }
+    changed.sort();
+    unmatched.sort();
+    suspicious.sort();
+
+    Ok(MigrationPlan {
+        changed,
+        unmatched,
+        suspicious,
+    })
+}
+
+/// Copies the metadata of the old ids to the new ones,
+/// values already attached to a new id are kept.
+/// Returns the amount of copied entries.
+///
+/// Either every storage is migrated or none: the values copied before
+/// a failure are removed again.
+pub fn migrate_metadata(
+    root: &PathBuf,
+    ids: &[(ResourceId, ResourceId)],
+) -> Result<usize, AppError> {
+    let mut writes = Vec::new();
+    for name in USER_STORAGES {
+        // The storage was never written
+        match translate_storage(&Some(root.to_owned()), name) {
+            Some((path, _)) if path.exists() => {}
+            _ => continue,
+        }
+        let mut storage = open_storage(root, name, &None)?;
+
+        let entries = storage
+            .entries()?
+            .into_iter()
+            .collect::<HashMap<_, _>>();
+        let values = ids
+            .iter()
+            .filter(|(_, new_id)| !entries.contains_key(new_id))
+            .filter_map(|(old_id, new_id)| {
+                Some((*new_id, entries.get(old_id)?.to_owned()))
+            })
+            .collect::<HashMap<_, _>>();
+        writes.push((name, values));
+    }
+
+    for (i, (name, values)) in writes.iter().enumerate() {
+        let result = open_storage(root, name, &None).and_then(|mut storage| {
+            let mut batch = storage.begin_batch();
+            for (id, value) in values {
+                batch.insert(*id, value)?;
+            }
+            batch.commit()
+        });
+
+        if let Err(e) = result {
+            for (name, values) in &writes[..=i] {
+                if let Err(e) = remove_values(root, name, values.keys()) {
+                    eprintln!(
+                        "Could not undo the migration of {}: {}",
+                        name, e
+                    );
+                }
+            }
+            return Err(e);
+        }
+    }
+
+    Ok(writes
+        .iter()
+        .map(|(_, values)| values.len())
+        .sum())
+}
+
+fn remove_values<'a>(
+    root: &PathBuf,
+    name: &str,
+    ids: impl Iterator<Item = &'a ResourceId>,
+) -> Result<usize, AppError> {
+    let mut storage = open_storage(root, name, &None)?;
+    let mut batch = storage.begin_batch();
+    for id in ids {
+        batch.remove(*id)?;
+    }
+    batch.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::format::Format;
+    use std::time::{Duration, SystemTime};
+    use tempfile::TempDir;
+
+    /// Writes the file as if it was done `ago` seconds ago,
+    /// so that the index tells it apart from the previous write
+    fn write(root: &Path, name: &str, content: &str, ago: u64) {
+        let path = root.join(name);
+        std::fs::write(&path, content).unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(SystemTime::now() - Duration::from_secs(ago))
+            .unwrap();
+    }
+
+    fn update_index(root: &PathBuf) -> HashMap<PathBuf, ResourceId> {
+        let rwlock = arklib::provide_index(root).unwrap();
+        let mut index = rwlock.write().unwrap();
+        index.update_all().unwrap();
+        index_snapshot(&index)
+    }
+
+    /// A root with `edited.txt`, `deleted.txt` and `kept.txt`,
+    /// and the snapshot taken of it
+    fn snapshotted() -> (TempDir, PathBuf, TempDir, PathBuf) {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path().canonicalize().unwrap();
+        write(&root, "edited.txt", "before", 100);
+        write(&root, "deleted.txt", "deleted", 100);
+        write(&root, "kept.txt", "kept", 100);
+
+        let snapshot_dir = TempDir::new().unwrap();
+        let snapshot = snapshot_dir.path().join("pre.json");
+        let taken = take_snapshot(&root).unwrap();
+        std::fs::write(&snapshot, taken.to_string()).unwrap();
+
+        (dir, root, snapshot_dir, snapshot)
+    }
+
+    #[test]
+    fn migrates_paths_changed_in_place() {
+        let (_dir, root, _snapshot_dir, snapshot) = snapshotted();
+        let before = update_index(&root);
+        let old_edited = before[&root.join("edited.txt")];
+        let old_deleted = before[&root.join("deleted.txt")];
+        let kept = before[&root.join("kept.txt")];
+
+        let mut tags = open_storage(&root, "tags", &None).unwrap();
+        tags.insert(old_edited, "edited", Format::Raw)
+            .unwrap();
+        tags.insert(old_deleted, "deleted", Format::Raw)
+            .unwrap();
+        tags.insert(kept, "kept", Format::Raw).unwrap();
+
+        write(&root, "edited.txt", "after optimizing", 50);
+        std::fs::remove_file(root.join("deleted.txt")).unwrap();
+        write(&root, "new.txt", "genuinely new", 50);
+        let after = update_index(&root);
+        let new_edited = after[&root.join("edited.txt")];
+        let new = after[&root.join("new.txt")];
+
+        let plan = migration_plan(&root, &snapshot).unwrap();
+        assert_eq!(
+            plan.changed,
+            vec![(PathBuf::from("edited.txt"), old_edited, new_edited)]
+        );
+        assert_eq!(plan.unmatched, vec![PathBuf::from("deleted.txt")]);
+        assert!(plan.suspicious.is_empty());
+
+        let ids = [(old_edited, new_edited)];
+        assert_eq!(migrate_metadata(&root, &ids).unwrap(), 1);
+        assert_eq!(tags.read(new_edited).unwrap(), "edited");
+        assert_eq!(tags.read(kept).unwrap(), "kept");
+        assert!(tags.read(new).is_err());
+
+        // Values already attached to the new id are kept
+        tags.insert(new_edited, "retagged", Format::Raw)
+            .unwrap();
+        assert_eq!(migrate_metadata(&root, &ids).unwrap(), 0);
+        assert_eq!(tags.read(new_edited).unwrap(), "retagged");
+    }
+
+    #[test]
+    fn leaves_inconsistent_paths_for_review() {
+        let (_dir, root, _snapshot_dir, snapshot) = snapshotted();
+        update_index(&root);
+
+        // Changed, but dated before the snapshot
+        write(&root, "edited.txt", "after optimizing", 200);
+        // Changed again after the index was updated
+        write(&root, "kept.txt", "kept, then changed", 50);
+        update_index(&root);
+        write(&root, "kept.txt", "kept, then changed twice", 40);
+
+        let plan = migration_plan(&root, &snapshot).unwrap();
+        assert!(plan.changed.is_empty());
+        assert!(plan.unmatched.is_empty());
+        let paths = plan
+            .suspicious
+            .iter()
+            .map(|(path, _)| path.to_owned())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            vec![PathBuf::from("edited.txt"), PathBuf::from("kept.txt")]
+        );
+        assert!(plan.suspicious[1].1.contains("update the index"));
+    }
+}
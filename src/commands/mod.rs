@@ -1,2 +1,17 @@
+pub mod backup;
+pub mod collisions;
+pub mod export;
 pub mod file;
+pub mod history;
+pub mod id;
+pub mod index;
 pub mod link;
+pub mod man;
+pub mod manifest;
+pub mod metadata;
+pub mod migrate;
+pub mod properties;
+pub mod render;
+pub mod roots;
+pub mod search;
+pub mod storage;
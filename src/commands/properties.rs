@@ -0,0 +1,66 @@
+use std::path::PathBuf;
+
+use arklib::id::ResourceId;
+use serde_json::{Map, Value};
+
+use crate::commands::storage::open_storage;
+use crate::error::AppError;
+use crate::models::format::Format;
+
+/// Properties of the resource, empty if it has none yet
+pub fn read_properties(
+    root: &PathBuf,
+    id: ResourceId,
+) -> Result<Map<String, Value>, AppError> {
+    let mut storage = open_storage(root, "properties", &None)?;
+    let value = match storage.read(id) {
+        Ok(value) => value,
+        Err(AppError::ResourceNotFound(_)) => return Ok(Map::new()),
+        Err(e) => return Err(e),
+    };
+
+    match serde_json::from_str(&value) {
+        Ok(Value::Object(properties)) => Ok(properties),
+        _ => Err(AppError::InvalidInput(format!(
+            "the properties of {} are not a JSON object",
+            id
+        ))),
+    }
+}
+
+/// Merges `key=value` pairs into the properties, later pairs win.
+/// An empty value sets the key to null.
+pub fn set_properties(
+    root: &PathBuf,
+    id: ResourceId,
+    pairs: &[String],
+    delete: &[String],
+) -> Result<Map<String, Value>, AppError> {
+    let mut properties = read_properties(root, id)?;
+
+    for pair in pairs {
+        let (key, value) = pair.split_once('=').ok_or_else(|| {
+            AppError::InvalidOptions(format!(
+                "expected key=value, got '{}'",
+                pair
+            ))
+        })?;
+        let value = match value {
+            "" => Value::Null,
+            value => Value::String(value.to_owned()),
+        };
+        properties.insert(key.to_owned(), value);
+    }
+    for key in delete {
+        properties.remove(key);
+    }
+
+    let mut storage = open_storage(root, "properties", &None)?;
+    storage.insert(
+        id,
+        &Value::Object(properties.clone()).to_string(),
+        Format::Raw,
+    )?;
+
+    Ok(properties)
+}
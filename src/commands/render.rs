@@ -0,0 +1,303 @@
+use std::fs::File;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::str::FromStr;
+
+use arklib::pdf::{render_preview_page, PDFQuality};
+use arklib::ARK_FOLDER;
+use image::{DynamicImage, ImageFormat};
+use walkdir::WalkDir;
+
+use crate::commands::id::compute_id;
+use crate::error::AppError;
+use crate::models::kind::Kind;
+use crate::models::render::RenderFormat;
+use crate::util::provide_config;
+
+/// Thumbnails of videos show the frame at this second by default
+const VIDEO_FRAME_AT: f64 = 1.0;
+
+/// Cached renders live next to the storages, but are not one:
+/// their names are not plain ids
+pub const RENDERS_CACHE_FOLDER: &str = "cache/renders";
+
+pub fn pdf_quality(quality: &str) -> Result<PDFQuality, AppError> {
+    match quality {
+        "high" => Ok(PDFQuality::High),
+        "medium" => Ok(PDFQuality::Medium),
+        "low" => Ok(PDFQuality::Low),
+        _ => Err(AppError::InvalidRenderOption),
+    }
+}
+
+fn is_pdf(path: &Path) -> bool {
+    path.extension()
+        .map(|ext| ext.eq_ignore_ascii_case("pdf"))
+        .unwrap_or(false)
+}
+
+/// Previews and thumbnails written by `destination`
+fn is_generated(path: &Path) -> bool {
+    path.file_stem()
+        .and_then(|stem| stem.to_str())
+        .map(|stem| stem.ends_with(".thumbnail") || stem.ends_with(".preview"))
+        .unwrap_or(false)
+}
+
+/// PDFs, videos and the image formats supported by the `image` crate
+pub fn is_renderable(path: &Path) -> bool {
+    is_pdf(path)
+        || ImageFormat::from_path(path).is_ok()
+        || Kind::of(path) == Kind::Video
+}
+
+// Frames are extracted with ffmpeg, which is not bundled
+fn extract_frame(path: &Path, at: f64) -> Result<DynamicImage, AppError> {
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error", "-ss", &at.to_string(), "-i"])
+        .arg(path)
+        .args(["-frames:v", "1", "-f", "image2pipe", "-vcodec", "png", "-"])
+        .output()
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound => AppError::RenderError(
+                "ffmpeg is not installed, videos are skipped".to_owned(),
+            ),
+            _ => AppError::RenderError(format!("Could not run ffmpeg: {}", e)),
+        })?;
+
+    if output.stdout.is_empty() {
+        // Videos shorter than the timestamp fall back to their first frame
+        if at > 0.0 {
+            return extract_frame(path, 0.0);
+        }
+        return Err(AppError::RenderError(format!(
+            "Could not extract a frame: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+
+    image::load_from_memory_with_format(&output.stdout, ImageFormat::Png)
+        .map_err(|e| {
+            AppError::RenderError(format!("Could not read the frame: {}", e))
+        })
+}
+
+// A directory output receives `<stem>.preview.<ext>` for PDFs and
+// `<stem>.thumbnail.<ext>` for images and videos, any other path is used
+// as is. The suffixes tell generated files apart from the user's ones.
+fn destination(
+    source: &Path,
+    output: &Option<PathBuf>,
+    format: RenderFormat,
+) -> Result<PathBuf, AppError> {
+    let stem = source
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| {
+            AppError::RenderError(format!(
+                "Invalid file name: {}",
+                source.display()
+            ))
+        })?;
+    let file_name = if is_pdf(source) {
+        format!("{}.preview.{}", stem, format.extension())
+    } else {
+        format!("{}.thumbnail.{}", stem, format.extension())
+    };
+
+    match output {
+        Some(output) if output.is_dir() => Ok(output.join(file_name)),
+        Some(output) => Ok(output.to_owned()),
+        None => Ok(source.with_file_name(file_name)),
+    }
+}
+
+// The first page of PDFs, a frame of videos, the image itself otherwise,
+// downscaled to fit `max_dimension` keeping the aspect ratio
+fn load_image(
+    path: &Path,
+    quality: &str,
+    max_dimension: Option<u32>,
+) -> Result<DynamicImage, AppError> {
+    let img = if is_pdf(path) {
+        let buf = File::open(path).map_err(|e| {
+            AppError::RenderError(format!("Could not open PDF: {}", e))
+        })?;
+        render_preview_page(buf, pdf_quality(quality)?)
+    } else if ImageFormat::from_path(path).is_err()
+        && Kind::of(path) == Kind::Video
+    {
+        let at = provide_config()
+            .video_frame_at
+            .unwrap_or(VIDEO_FRAME_AT);
+        extract_frame(path, at)?
+    } else {
+        image::open(path).map_err(|e| {
+            AppError::RenderError(format!("Could not open image: {}", e))
+        })?
+    };
+
+    Ok(match max_dimension {
+        Some(max) if img.width() > max || img.height() > max => {
+            img.thumbnail(max, max)
+        }
+        _ => img,
+    })
+}
+
+pub fn render_file(
+    path: &Path,
+    quality: &str,
+    output: &Option<PathBuf>,
+    format: Option<RenderFormat>,
+    max_dimension: Option<u32>,
+    force: bool,
+) -> Result<PathBuf, AppError> {
+    let format = format
+        .or_else(|| {
+            output
+                .as_ref()
+                .filter(|output| !output.is_dir())
+                .and_then(|output| output.extension())
+                .and_then(|ext| ext.to_str())
+                .and_then(|ext| RenderFormat::from_str(ext).ok())
+        })
+        .unwrap_or(RenderFormat::Png);
+
+    let dest_path = destination(path, output, format)?;
+    if dest_path == path {
+        return Err(AppError::RenderError(format!(
+            "Refusing to overwrite the source {}",
+            path.display()
+        )));
+    }
+    if dest_path.exists() && !force {
+        return Err(AppError::RenderError(format!(
+            "{} already exists, pass --force to overwrite it",
+            dest_path.display()
+        )));
+    }
+
+    let img = load_image(path, quality, max_dimension)?;
+    let img = match format {
+        // JPEG has no alpha channel
+        RenderFormat::Jpeg => DynamicImage::ImageRgb8(img.to_rgb8()),
+        _ => img,
+    };
+    img.save_with_format(&dest_path, format.image_format())
+        .map_err(|e| {
+            AppError::RenderError(format!(
+                "Could not save preview to {}: {}",
+                dest_path.display(),
+                e
+            ))
+        })?;
+
+    Ok(dest_path)
+}
+
+/// Renders into the renders cache of the root, named by the id of the
+/// file (and the maximal dimension, if any), unless the preview is
+/// cached already.
+/// Returns the path of the preview and whether it was cached.
+pub fn render_cached(
+    path: &Path,
+    quality: &str,
+    root: &Path,
+    format: Option<RenderFormat>,
+    max_dimension: Option<u32>,
+) -> Result<(PathBuf, bool), AppError> {
+    let format = format.unwrap_or(RenderFormat::Png);
+    let cache = root.join(ARK_FOLDER).join(RENDERS_CACHE_FOLDER);
+    let id = compute_id(path)?;
+    let cached = cache.join(match max_dimension {
+        Some(max) => format!("{}-{}.{}", id, max, format.extension()),
+        None => format!("{}.{}", id, format.extension()),
+    });
+    if cached.is_file() {
+        return Ok((cached, true));
+    }
+
+    std::fs::create_dir_all(&cache)?;
+    let rendered = render_file(
+        path,
+        quality,
+        &Some(cached),
+        Some(format),
+        max_dimension,
+        false,
+    )?;
+    Ok((rendered, false))
+}
+
+pub fn render_dir(
+    dir: &Path,
+    quality: &str,
+    output: &Option<PathBuf>,
+    format: Option<RenderFormat>,
+    max_dimension: Option<u32>,
+    cache: Option<&Path>,
+    force: bool,
+) -> Result<(), AppError> {
+    pdf_quality(quality)?;
+
+    if let Some(output) = output {
+        if !output.is_dir() {
+            return Err(AppError::RenderError(format!(
+                "Output must be an existing directory when rendering a \
+                 directory: {}",
+                output.display()
+            )));
+        }
+    }
+
+    let (mut rendered, mut cached, mut failed) = (0, 0, 0);
+
+    // Collected upfront, so that the rendered files are not walked into
+    let sources: Vec<PathBuf> = WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .filter(|path| is_renderable(path) && !is_generated(path))
+        .collect();
+
+    for source in sources {
+        let result = match cache {
+            Some(root) => {
+                render_cached(&source, quality, root, format, max_dimension)
+            }
+            None => render_file(
+                &source,
+                quality,
+                output,
+                format,
+                max_dimension,
+                force,
+            )
+            .map(|dest_path| (dest_path, false)),
+        };
+        match result {
+            Ok((dest_path, true)) => {
+                println!("Cached {}", dest_path.display());
+                cached += 1;
+            }
+            Ok((dest_path, false)) => {
+                println!("Rendered {}", dest_path.display());
+                rendered += 1;
+            }
+            Err(e) => {
+                eprintln!("Failed to render {}: {}", source.display(), e);
+                failed += 1;
+            }
+        }
+    }
+
+    println!(
+        "Rendered {} files, {} were cached, {} failed",
+        rendered, cached, failed
+    );
+
+    Ok(())
+}
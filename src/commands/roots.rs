@@ -0,0 +1,86 @@
+use std::fs::{create_dir_all, File};
+use std::path::{Path, PathBuf};
+
+use arklib::ARK_FOLDER;
+
+use crate::error::AppError;
+use crate::util::parse_roots;
+
+pub fn read_roots(config: &Path) -> Result<Vec<PathBuf>, AppError> {
+    match File::open(config) {
+        Ok(file) => Ok(parse_roots(file)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn write_roots(config: &Path, roots: &[PathBuf]) -> Result<(), AppError> {
+    if let Some(parent) = config.parent() {
+        create_dir_all(parent)?;
+    }
+
+    let contents = roots
+        .iter()
+        .map(|root| format!("{}\n", root.display()))
+        .collect::<String>();
+
+    Ok(std::fs::write(config, contents)?)
+}
+
+/// Registers the canonicalized root, returns it
+pub fn add_root(
+    config: &Path,
+    path: &Path,
+    init: bool,
+) -> Result<PathBuf, AppError> {
+    let root = path.canonicalize().map_err(|e| {
+        AppError::RootsConfigError(format!("{}: {}", path.display(), e))
+    })?;
+    if !root.is_dir() {
+        return Err(AppError::RootsConfigError(format!(
+            "{} is not a directory",
+            root.display()
+        )));
+    }
+
+    let mut roots = read_roots(config)?;
+    if roots.contains(&root) {
+        return Err(AppError::RootsConfigError(format!(
+            "{} is already registered",
+            root.display()
+        )));
+    }
+
+    if init {
+        create_dir_all(root.join(ARK_FOLDER))?;
+    }
+
+    roots.push(root.clone());
+    write_roots(config, &roots)?;
+
+    Ok(root)
+}
+
+pub fn remove_root(config: &Path, path: &Path) -> Result<PathBuf, AppError> {
+    // The root could have been deleted already,
+    // or registered by hand without canonicalization
+    let root = path
+        .canonicalize()
+        .unwrap_or_else(|_| path.to_path_buf());
+
+    let mut roots = read_roots(config)?;
+    let position = roots
+        .iter()
+        .position(|registered| registered == &root || registered == path)
+        .ok_or_else(|| {
+            AppError::RootsConfigError(format!(
+                "{} is not registered",
+                path.display()
+            ))
+        })?;
+
+    let removed = roots.remove(position);
+    write_roots(config, &roots)?;
+
+    Ok(removed)
+}
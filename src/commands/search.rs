@@ -0,0 +1,182 @@
+use std::fs::read;
+use std::path::PathBuf;
+
+use arklib::id::ResourceId;
+use regex::{Regex, RegexBuilder};
+
+use crate::commands::storage::open_storage;
+use crate::error::AppError;
+use crate::models::kind::mime_type;
+use crate::util::{provide_index, translate_storage};
+
+/// Searched when no storage is given. Links are the `.link` resources,
+/// their titles and descriptions are in the properties.
+pub const SEARCH_STORAGES: [&str; 3] = ["tags", "properties", "links"];
+
+/// Characters of context shown on each side of a match
+const SNIPPET_CONTEXT: usize = 30;
+
+pub struct SearchMatch {
+    pub id: ResourceId,
+    /// Storage name, or `content` for matches in the resource itself
+    pub source: &'static str,
+    pub snippet: String,
+}
+
+impl SearchMatch {
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id.to_string(),
+            "storage": self.source,
+            "snippet": self.snippet,
+        })
+    }
+}
+
+/// The query is matched literally unless `regex` is given
+pub fn compile_query(
+    query: &str,
+    regex: bool,
+    case_sensitive: bool,
+) -> Result<Regex, AppError> {
+    let pattern = if regex {
+        query.to_owned()
+    } else {
+        regex::escape(query)
+    };
+
+    RegexBuilder::new(&pattern)
+        .case_insensitive(!case_sensitive)
+        .build()
+        .map_err(|e| AppError::InvalidRegex(e.to_string()))
+}
+
+/// Matches line by line in the values of the storages and, with
+/// `content_limit`, in text resources of at most that many bytes
+pub fn search(
+    root: &PathBuf,
+    query: &Regex,
+    storages: &[String],
+    content_limit: Option<u64>,
+) -> Result<Vec<SearchMatch>, AppError> {
+    let mut sources = Vec::new();
+    for name in storages {
+        match SEARCH_STORAGES
+            .iter()
+            .find(|known| known.eq_ignore_ascii_case(name))
+        {
+            Some(known) => sources.push(*known),
+            None => {
+                return Err(AppError::InvalidOptions(format!(
+                    "cannot search storage '{}', expected one of: {}",
+                    name,
+                    SEARCH_STORAGES.join(", ")
+                )))
+            }
+        }
+    }
+    if sources.is_empty() {
+        sources.extend(SEARCH_STORAGES);
+    }
+
+    let mut matches = Vec::new();
+    for source in sources
+        .iter()
+        .copied()
+        .filter(|source| *source != "links")
+    {
+        // Storages never written to have nothing to match
+        match translate_storage(&Some(root.to_owned()), source) {
+            Some((path, _)) if path.exists() => {}
+            _ => continue,
+        }
+
+        for (id, value) in open_storage(root, source, &None)?.entries()? {
+            match_lines(&mut matches, query, id, source, &value);
+        }
+    }
+
+    if sources.contains(&"links") || content_limit.is_some() {
+        let index = provide_index(root);
+        for (path, entry) in index.path2id.iter() {
+            let path = path.as_path();
+            let is_link = path
+                .extension()
+                .map(|ext| ext.eq_ignore_ascii_case("link"))
+                .unwrap_or(false);
+
+            let source = if is_link && sources.contains(&"links") {
+                "links"
+            } else if !is_link
+                && content_limit
+                    .is_some_and(|limit| entry.id.data_size <= limit)
+                && mime_type(path).is_some_and(|mime| mime.starts_with("text/"))
+            {
+                "content"
+            } else {
+                continue;
+            };
+
+            // Files deleted since indexing are skipped
+            let data = match read(path) {
+                Ok(data) => data,
+                Err(_) => continue,
+            };
+            let text = String::from_utf8_lossy(&data);
+            match_lines(&mut matches, query, entry.id, source, &text);
+        }
+    }
+
+    matches.sort_by(|a, b| {
+        (a.id.to_string(), a.source).cmp(&(b.id.to_string(), b.source))
+    });
+    Ok(matches)
+}
+
+fn match_lines(
+    matches: &mut Vec<SearchMatch>,
+    query: &Regex,
+    id: ResourceId,
+    source: &'static str,
+    text: &str,
+) {
+    for line in text.lines() {
+        if let Some(found) = query.find(line) {
+            matches.push(SearchMatch {
+                id,
+                source,
+                snippet: snippet(line, found.start(), found.end()),
+            });
+        }
+    }
+}
+
+// The match with some context around it, cut on character boundaries
+fn snippet(line: &str, start: usize, end: usize) -> String {
+    let from = line[..start]
+        .char_indices()
+        .rev()
+        .nth(SNIPPET_CONTEXT - 1)
+        .map(|(i, _)| i)
+        .unwrap_or(0);
+    let to = line[end..]
+        .char_indices()
+        .nth(SNIPPET_CONTEXT)
+        .map(|(i, _)| end + i)
+        .unwrap_or(line.len());
+
+    format!(
+        "{}{}{}",
+        if from > 0 {
+            "..."
+        } else {
+            ""
+        },
+        line[from..to].trim(),
+        if to < line.len() {
+            "..."
+        } else {
+            ""
+        }
+    )
+}
@@ -0,0 +1,575 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::io::{stdin, Read, Write};
+use std::path::{Path, PathBuf};
+
+use arklib::id::ResourceId;
+use serde_json::{Map, Value};
+
+use crate::commands::id::parse_id;
+use crate::error::AppError;
+use crate::models::format::Format;
+use crate::models::storage::{
+    MergeStrategy, Storage, StorageType, SyncPreference,
+};
+use crate::util::{confirm, resolve_storage, timestamp};
+
+pub fn open_storage(
+    root: &PathBuf,
+    storage: &str,
+    type_: &Option<StorageType>,
+) -> Result<Storage, AppError> {
+    let (file_path, storage_type) = resolve_storage(root, storage, type_)?;
+
+    Storage::new(file_path, storage_type)
+}
+
+pub fn dump_storage(storage: &mut Storage) -> Result<Value, AppError> {
+    let dump = storage
+        .entries()?
+        .into_iter()
+        .map(|(id, value)| {
+            // Structured values are dumped as is to be editable with jq
+            let value = match serde_json::from_str(&value) {
+                Ok(Value::Object(object)) => Value::Object(object),
+                _ => Value::String(value),
+            };
+            (id.to_string(), value)
+        })
+        .collect::<Map<_, _>>();
+
+    Ok(Value::Object(dump))
+}
+
+pub fn import_storage(
+    storage: &mut Storage,
+    input: &Option<PathBuf>,
+    replace: bool,
+    dry_run: bool,
+) -> Result<usize, AppError> {
+    let mut data = String::new();
+    match input {
+        Some(path) => File::open(path)?.read_to_string(&mut data)?,
+        None => stdin().read_to_string(&mut data)?,
+    };
+
+    let entries = match serde_json::from_str(&data) {
+        Ok(Value::Object(entries)) => entries,
+        Ok(_) => {
+            return Err(AppError::ImportError(
+                "Expected a JSON object mapping ids to values".to_owned(),
+            ))
+        }
+        Err(e) => return Err(AppError::ImportError(e.to_string())),
+    };
+
+    let existing = storage
+        .entries()?
+        .into_iter()
+        .map(|(id, _)| id)
+        .collect::<HashSet<_>>();
+
+    // Checked upfront, so that a dry run fails the same way
+    let mut values = Vec::new();
+    for (id, value) in entries {
+        let id = parse_id(&id)?;
+        if !replace && existing.contains(&id) {
+            continue;
+        }
+
+        let value = match value {
+            Value::String(value) => value,
+            value => value.to_string(),
+        };
+        storage.check_value(id, &value)?;
+        values.push((id, value));
+    }
+
+    if dry_run {
+        for (id, value) in &values {
+            println!("{}: {}", id, value);
+        }
+        return Ok(values.len());
+    }
+
+    let mut batch = storage.begin_batch();
+    for (id, value) in values {
+        batch.insert(id, &value)?;
+    }
+    batch.commit()
+}
+
+pub struct StorageDiff {
+    pub only_a: Vec<(ResourceId, String)>,
+    pub only_b: Vec<(ResourceId, String)>,
+    /// Ids with their values in A and in B
+    pub differing: Vec<(ResourceId, String, String)>,
+}
+
+pub fn diff_storages(
+    a: &mut Storage,
+    b: &mut Storage,
+) -> Result<StorageDiff, AppError> {
+    let entries_a = a
+        .entries()?
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+    let entries_b = b
+        .entries()?
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+
+    let mut diff = StorageDiff {
+        only_a: Vec::new(),
+        only_b: Vec::new(),
+        differing: Vec::new(),
+    };
+    for (id, value) in &entries_a {
+        match entries_b.get(id) {
+            None => diff.only_a.push((*id, value.clone())),
+            Some(other) if other != value => {
+                diff.differing
+                    .push((*id, value.clone(), other.clone()))
+            }
+            Some(_) => {}
+        }
+    }
+    for (id, value) in &entries_b {
+        if !entries_a.contains_key(id) {
+            diff.only_b.push((*id, value.clone()));
+        }
+    }
+
+    diff.only_a.sort_by_key(|(id, _)| id.to_string());
+    diff.only_b.sort_by_key(|(id, _)| id.to_string());
+    diff.differing
+        .sort_by_key(|(id, _, _)| id.to_string());
+
+    Ok(diff)
+}
+
+/// Inserts values read from stdin, either `<id>\t<value>` lines
+/// or a JSON object mapping ids to values, with a single storage write.
+/// Lines which fail to parse are reported and skipped.
+/// Returns the amount of inserted and of failed entries.
+pub fn bulk_insert(
+    storage: &mut Storage,
+    dry_run: bool,
+) -> Result<(usize, usize), AppError> {
+    let mut data = String::new();
+    stdin().read_to_string(&mut data)?;
+
+    let mut entries = Vec::new();
+    let mut failed = 0;
+    if data.trim_start().starts_with('{') {
+        let object = match serde_json::from_str(&data) {
+            Ok(Value::Object(object)) => object,
+            Ok(_) => {
+                return Err(AppError::ImportError(
+                    "expected a JSON object mapping ids to values".to_owned(),
+                ))
+            }
+            Err(e) => return Err(AppError::ImportError(e.to_string())),
+        };
+        for (id, value) in object {
+            let value = match value {
+                Value::String(value) => value,
+                value => value.to_string(),
+            };
+            let parsed = parse_id(&id).and_then(|id| {
+                storage.check_value(id, &value)?;
+                Ok((id, value))
+            });
+            match parsed {
+                Ok(entry) => entries.push(entry),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    failed += 1;
+                }
+            }
+        }
+    } else {
+        for (i, line) in data.lines().enumerate() {
+            // `lines` keeps the `\r` of a last line without `\n`
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let parsed = line
+                .split_once('\t')
+                .ok_or_else(|| "expected <id>\t<value>".to_owned())
+                .and_then(|(id, value)| {
+                    let id = parse_id(id).map_err(|e| e.to_string())?;
+                    storage
+                        .check_value(id, value)
+                        .map_err(|e| e.to_string())?;
+                    Ok((id, value.to_owned()))
+                });
+            match parsed {
+                Ok(entry) => entries.push(entry),
+                Err(e) => {
+                    eprintln!("line {}: {}", i + 1, e);
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    if dry_run {
+        for (id, value) in &entries {
+            println!("{}: {}", id, value);
+        }
+        return Ok((entries.len(), failed));
+    }
+
+    let mut batch = storage.begin_batch();
+    for (id, value) in &entries {
+        batch.insert(*id, value)?;
+    }
+
+    Ok((batch.commit()?, failed))
+}
+
+pub fn sync_storages(
+    a: &mut Storage,
+    b: &mut Storage,
+    prefer: SyncPreference,
+    dry_run: bool,
+) -> Result<(), AppError> {
+    let diff = diff_storages(a, b)?;
+    let mut to_a = diff.only_b;
+    let mut to_b = diff.only_a;
+
+    for (id, value_a, value_b) in &diff.differing {
+        let prefer_a = match prefer {
+            SyncPreference::A => true,
+            SyncPreference::B => false,
+            SyncPreference::Newer => a.modified(*id) >= b.modified(*id),
+            SyncPreference::Fail => {
+                println!("conflict {}: a = {}, b = {}", id, value_a, value_b);
+                continue;
+            }
+        };
+
+        if prefer_a {
+            to_b.push((*id, value_a.clone()));
+        } else {
+            to_a.push((*id, value_b.clone()));
+        }
+    }
+
+    if prefer == SyncPreference::Fail && !diff.differing.is_empty() {
+        return Err(AppError::StorageSyncError(format!(
+            "{} entries differ between the roots",
+            diff.differing.len()
+        )));
+    }
+
+    to_a.sort_by_key(|(id, _)| id.to_string());
+    to_b.sort_by_key(|(id, _)| id.to_string());
+
+    for (id, value) in &to_b {
+        println!("a -> b {}: {}", id, value);
+    }
+    for (id, value) in &to_a {
+        println!("b -> a {}: {}", id, value);
+    }
+
+    if dry_run {
+        return Ok(());
+    }
+
+    let mut batch = b.begin_batch();
+    for (id, value) in to_b {
+        batch.insert(id, &value)?;
+    }
+    batch.commit()?;
+
+    let mut batch = a.begin_batch();
+    for (id, value) in to_a {
+        batch.insert(id, &value)?;
+    }
+    batch.commit()?;
+
+    Ok(())
+}
+
+/// Copies entries of `src` missing in `dst`, entries present in both
+/// are resolved by the strategy. Returns the amount of written entries.
+pub fn merge_storages(
+    src: &mut Storage,
+    dst: &mut Storage,
+    strategy: MergeStrategy,
+    dry_run: bool,
+) -> Result<usize, AppError> {
+    let diff = diff_storages(src, dst)?;
+    let mut writes = diff.only_a;
+
+    for (id, value_src, _) in diff.differing {
+        let take_src = match strategy {
+            MergeStrategy::KeepDst => false,
+            MergeStrategy::KeepSrc => true,
+            MergeStrategy::HigherVersion => src.version(id) > dst.version(id),
+            MergeStrategy::Newer => src.modified(id) > dst.modified(id),
+        };
+        if take_src {
+            writes.push((id, value_src));
+        }
+    }
+
+    writes.sort_by_key(|(id, _)| id.to_string());
+    for (id, value) in &writes {
+        println!("src -> dst {}: {}", id, value);
+    }
+
+    if dry_run {
+        return Ok(writes.len());
+    }
+
+    // Entries changed in `dst` while merging are resolved the same way
+    let mut batch = dst.begin_batch().on_conflict(strategy);
+    for (id, value) in &writes {
+        batch.insert(*id, value)?;
+    }
+    batch.commit()
+}
+
+/// Entries of ids which are not indexed anymore
+pub fn find_orphans(
+    storage: &mut Storage,
+    indexed: &HashSet<ResourceId>,
+) -> Result<Vec<(ResourceId, String)>, AppError> {
+    let mut orphans = storage
+        .entries()?
+        .into_iter()
+        .filter(|(id, _)| !indexed.contains(id))
+        .collect::<Vec<_>>();
+    orphans.sort_by_key(|(id, _)| id.to_string());
+
+    Ok(orphans)
+}
+
+pub fn remove_entries(
+    storage: &mut Storage,
+    ids: &[ResourceId],
+) -> Result<usize, AppError> {
+    let mut batch = storage.begin_batch();
+    for id in ids {
+        batch.remove(*id)?;
+    }
+    batch.commit()
+}
+
+pub struct StorageUsage {
+    pub total: u64,
+    pub count: usize,
+    pub mean: u64,
+    pub median: u64,
+    pub max: u64,
+    /// The biggest values, largest first
+    pub largest: Vec<(ResourceId, u64)>,
+}
+
+pub fn storage_usage(
+    storage: &mut Storage,
+    top: usize,
+) -> Result<StorageUsage, AppError> {
+    let mut sizes = storage.value_sizes()?;
+    sizes.sort_by(|(a_id, a), (b_id, b)| {
+        b.cmp(a)
+            .then_with(|| a_id.to_string().cmp(&b_id.to_string()))
+    });
+
+    let count = sizes.len();
+    let total = sizes.iter().map(|(_, size)| size).sum::<u64>();
+    let median = match count {
+        0 => 0,
+        _ if count % 2 == 0 => {
+            (sizes[count / 2 - 1].1 + sizes[count / 2].1) / 2
+        }
+        _ => sizes[count / 2].1,
+    };
+
+    Ok(StorageUsage {
+        total,
+        count,
+        mean: if count == 0 {
+            0
+        } else {
+            total / count as u64
+        },
+        median,
+        max: sizes.first().map(|(_, size)| *size).unwrap_or(0),
+        largest: sizes.into_iter().take(top).collect(),
+    })
+}
+
+/// Journal of truncated values inside `.ark`, a JSON object per line
+pub const TRUNCATE_JOURNAL: &str = "truncate-journal";
+
+/// Cuts the value down to `max_bytes`, keeping whole characters.
+/// The original value is appended to `journal` first, so that it can be
+/// restored later.
+pub fn truncate_value(
+    storage: &mut Storage,
+    storage_name: &str,
+    id: ResourceId,
+    max_bytes: usize,
+    journal: &Path,
+    yes: bool,
+    dry_run: bool,
+) -> Result<(), AppError> {
+    let value = storage.read(id)?;
+    if value.len() <= max_bytes {
+        println!("The value of {} is {} bytes already", id, value.len());
+        return Ok(());
+    }
+
+    let mut end = max_bytes;
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    let question = format!(
+        "Truncate the value of {} from {} to {} bytes?",
+        id,
+        value.len(),
+        end
+    );
+    if dry_run {
+        println!("Would truncate the value of {} to {} bytes", id, end);
+        return Ok(());
+    }
+    if !yes && !confirm(&question)? {
+        println!("Aborted.");
+        return Ok(());
+    }
+
+    if let Some(parent) = journal.parent() {
+        create_dir_all(parent)?;
+    }
+    let record = serde_json::json!({
+        "time": timestamp().as_secs(),
+        "storage": storage_name,
+        "id": id.to_string(),
+        "truncated_to": end,
+        "value": value,
+    });
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(journal)?;
+    writeln!(file, "{}", record)?;
+    file.sync_all()?;
+
+    storage.insert(id, &value[..end], Format::Raw)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn id(n: u64) -> ResourceId {
+        format!("{}-{}", n, n).parse().unwrap()
+    }
+
+    /// A storage with values of 3 bytes, 10 bytes and `oversized` bytes
+    fn planted(
+        dir: &TempDir,
+        storage_type: StorageType,
+        oversized: usize,
+    ) -> Storage {
+        let mut storage =
+            Storage::new(dir.path().join("storage"), storage_type).unwrap();
+        storage.insert(id(1), "abc", Format::Raw).unwrap();
+        storage
+            .insert(id(2), &"x".repeat(10), Format::Raw)
+            .unwrap();
+        storage
+            .insert(id(3), &"é".repeat(oversized / 2), Format::Raw)
+            .unwrap();
+        storage
+    }
+
+    #[test]
+    fn storage_usage_finds_oversized_values() {
+        for (storage_type, oversized) in [
+            (StorageType::File, 100_000),
+            (StorageType::Folder, 4_000_000),
+        ] {
+            let dir = TempDir::new().unwrap();
+            let mut storage = planted(&dir, storage_type, oversized);
+
+            let usage = storage_usage(&mut storage, 2).unwrap();
+            let total = (3 + 10 + oversized) as u64;
+            assert_eq!(usage.count, 3);
+            assert_eq!(usage.total, total);
+            assert_eq!(usage.mean, total / 3);
+            assert_eq!(usage.median, 10);
+            assert_eq!(usage.max, oversized as u64);
+            assert_eq!(
+                usage.largest,
+                vec![(id(3), oversized as u64), (id(2), 10)]
+            );
+        }
+    }
+
+    #[test]
+    fn storage_usage_of_empty_storage() {
+        let dir = TempDir::new().unwrap();
+        let mut storage =
+            Storage::new(dir.path().join("storage"), StorageType::File)
+                .unwrap();
+
+        let usage = storage_usage(&mut storage, 5).unwrap();
+        assert_eq!((usage.count, usage.total, usage.median), (0, 0, 0));
+        assert!(usage.largest.is_empty());
+    }
+
+    #[test]
+    fn truncate_value_keeps_whole_characters() {
+        for storage_type in [StorageType::File, StorageType::Folder] {
+            let dir = TempDir::new().unwrap();
+            let mut storage = planted(&dir, storage_type, 100_000);
+            let journal = dir.path().join(TRUNCATE_JOURNAL);
+            let truncate = |storage: &mut Storage, id, dry_run| {
+                truncate_value(
+                    storage,
+                    "properties",
+                    id,
+                    101,
+                    &journal,
+                    true,
+                    dry_run,
+                )
+                .unwrap()
+            };
+
+            truncate(&mut storage, id(3), true);
+            assert_eq!(storage.read(id(3)).unwrap().len(), 100_000);
+            assert!(!journal.exists());
+
+            // 101 bytes would split the two-byte 'é'
+            truncate(&mut storage, id(3), false);
+            assert_eq!(storage.read(id(3)).unwrap(), "é".repeat(50));
+
+            truncate(&mut storage, id(1), false);
+            assert_eq!(storage.read(id(1)).unwrap(), "abc");
+            assert_eq!(storage.read(id(2)).unwrap(), "x".repeat(10));
+
+            // Only the truncated value is journaled, in full
+            let journal = std::fs::read_to_string(&journal).unwrap();
+            let records = journal
+                .lines()
+                .map(|line| serde_json::from_str::<Value>(line).unwrap())
+                .collect::<Vec<_>>();
+            assert_eq!(records.len(), 1);
+            assert_eq!(records[0]["storage"], "properties");
+            assert_eq!(records[0]["id"], id(3).to_string());
+            assert_eq!(records[0]["truncated_to"], 100);
+            assert_eq!(records[0]["value"], "é".repeat(50_000));
+        }
+    }
+}
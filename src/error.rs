@@ -1,3 +1,4 @@
+use arklib::id::ResourceId;
 use arklib::ArklibError;
 use std::io;
 use thiserror::Error;
@@ -31,21 +32,86 @@ pub enum AppError {
     #[error("Could not load link: {0}")]
     LinkLoadError(String),
 
+    #[error("Could not open link: {0}")]
+    LinkOpenError(String),
+
     #[error("File operation error: {0}")]
     FileOperationError(String),
 
+    #[error("Could not import storage: {0}")]
+    ImportError(String),
+
+    #[error("Could not sync storages: {0}")]
+    StorageSyncError(String),
+
+    #[error("Could not read manifest: {0}")]
+    ManifestError(String),
+
+    #[error("Could not update roots config: {0}")]
+    RootsConfigError(String),
+
+    #[error("Invalid config: {0}")]
+    ConfigError(String),
+
     #[error("Failed to create backup: {0}")]
     BackupCreationError(String),
 
     #[error("Unknown render option")]
     InvalidRenderOption,
 
+    #[error("Failed to render preview: {0}")]
+    RenderError(String),
+
+    #[error("Could not extract metadata: {0}")]
+    MetadataError(String),
+
     #[error("Storage not found: {0}")]
     StorageNotFound(String),
 
+    #[error("Invalid id {0}, expected <size>-<hash> like 22-207093268")]
+    InvalidId(String),
+
+    #[error("Invalid id prefix: {0}")]
+    InvalidIdPrefix(String),
+
+    #[error("Resource not found: {0}")]
+    ResourceNotFound(ResourceId),
+
+    #[error("Key not found: {0}")]
+    KeyNotFound(String),
+
+    #[error("Network error: {0}")]
+    NetworkError(String),
+
+    #[error("Invalid time '{0}', expected a date (2024-01-01), an RFC 3339 timestamp or a relative duration (7d, 12h)")]
+    InvalidTime(String),
+
+    #[error("Invalid date format '{0}', see strftime specifiers of chrono")]
+    InvalidDateFormat(String),
+
+    #[error("Invalid glob pattern {0}")]
+    InvalidGlob(String),
+
+    #[error("Invalid regular expression: {0}")]
+    InvalidRegex(String),
+
+    #[error("Invalid options: {0}")]
+    InvalidOptions(String),
+
+    #[error("Invalid input: {0}")]
+    InvalidInput(String),
+
     #[error("Invalid entry option")]
     InvalidEntryOption,
 
+    /// A check found problems, the command itself worked
+    #[error("{0}")]
+    Findings(String),
+
+    /// Files differ from the manifest listing them
+    #[error("{0}")]
+    Mismatch(String),
+
     #[error(transparent)]
     IoError(#[from] io::Error),
 
@@ -55,3 +121,129 @@ pub enum AppError {
     #[error(transparent)]
     InlineJsonParseError(#[from] InlineJsonParseError),
 }
+
+impl AppError {
+    /// Exit codes follow `sysexits.h`, so that scripts can tell
+    /// wrong input from missing data or failing environment
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            AppError::Findings(_) => 1,
+            AppError::Mismatch(_) => MISMATCH_EXIT_CODE,
+            AppError::InvalidRenderOption
+            | AppError::InvalidEntryOption
+            | AppError::InvalidTime(_)
+            | AppError::InvalidGlob(_)
+            | AppError::InvalidRegex(_)
+            | AppError::InvalidDateFormat(_)
+            | AppError::InvalidOptions(_)
+            | AppError::InvalidId(_)
+            | AppError::InlineJsonParseError(_) => 64,
+            AppError::IndexError(_)
+            | AppError::LinkCreationError(_)
+            | AppError::ImportError(_)
+            | AppError::StorageSyncError(_)
+            | AppError::ManifestError(_)
+            | AppError::RenderError(_)
+            | AppError::MetadataError(_)
+            | AppError::InvalidIdPrefix(_)
+            | AppError::InvalidInput(_) => 65,
+            AppError::StorageNotFound(_)
+            | AppError::ResourceNotFound(_)
+            | AppError::KeyNotFound(_)
+            | AppError::LinkLoadError(_) => 66,
+            AppError::NetworkError(_) | AppError::LinkOpenError(_) => 69,
+            AppError::ArklibError(_) => 70,
+            AppError::ArkDirectoryCreationError(_)
+            | AppError::StorageCreationError(_)
+            | AppError::BackupCreationError(_) => 73,
+            AppError::IoError(e) if e.kind() == io::ErrorKind::BrokenPipe => {
+                BROKEN_PIPE_EXIT_CODE
+            }
+            AppError::FileOperationError(_) | AppError::IoError(_) => 74,
+            AppError::HomeDirNotFound
+            | AppError::AppIdLoadError(_)
+            | AppError::RootsConfigError(_)
+            | AppError::ConfigError(_) => 78,
+        }
+    }
+}
+
+/// Manifest entries with different content, unlike the other findings
+/// which exit with 1, and unlike usage errors which clap exits with 2
+pub const MISMATCH_EXIT_CODE: i32 = 3;
+
+/// The reader of the output went away, e.g. `ark-cli list | head`.
+/// Same as being killed by SIGPIPE, as shells report it.
+pub const BROKEN_PIPE_EXIT_CODE: i32 = 141;
+
+/// Exit code of any error reaching `main`: stray I/O errors are
+/// I/O errors too, anything else is a generic failure
+pub fn exit_code(error: &anyhow::Error) -> i32 {
+    error
+        .chain()
+        .find_map(|cause| {
+            if let Some(e) = cause.downcast_ref::<AppError>() {
+                Some(e.exit_code())
+            } else {
+                cause.downcast_ref::<io::Error>().map(|e| {
+                    if e.kind() == io::ErrorKind::BrokenPipe {
+                        BROKEN_PIPE_EXIT_CODE
+                    } else {
+                        74
+                    }
+                })
+            }
+        })
+        .unwrap_or(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn app_errors_map_to_sysexits() {
+        let cases = [
+            (AppError::InvalidTime("3y".to_owned()), 64),
+            (AppError::ManifestError("bad".to_owned()), 65),
+            (AppError::StorageNotFound("tags".to_owned()), 66),
+            (AppError::NetworkError("down".to_owned()), 69),
+            (AppError::Findings("no matches".to_owned()), 1),
+            (AppError::Mismatch("1 file differs".to_owned()), 3),
+            (AppError::BackupCreationError("full".to_owned()), 73),
+            (AppError::IoError(io::Error::other("disk")), 74),
+            (AppError::HomeDirNotFound, 78),
+        ];
+        for (error, code) in cases {
+            assert_eq!(error.exit_code(), code, "{}", error);
+            assert_eq!(exit_code(&error.into()), code);
+        }
+    }
+
+    #[test]
+    fn stray_io_errors_exit_with_74() {
+        let error = anyhow::Error::new(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "denied",
+        ));
+        assert_eq!(exit_code(&error), 74);
+        assert_eq!(exit_code(&error.context("while listing")), 74);
+    }
+
+    #[test]
+    fn broken_pipes_exit_like_sigpipe() {
+        let closed = || io::Error::from(io::ErrorKind::BrokenPipe);
+        assert_eq!(exit_code(&closed().into()), BROKEN_PIPE_EXIT_CODE);
+        assert_eq!(
+            exit_code(&AppError::IoError(closed()).into()),
+            BROKEN_PIPE_EXIT_CODE
+        );
+    }
+
+    #[test]
+    fn other_errors_exit_with_1() {
+        assert_eq!(exit_code(&anyhow::anyhow!("unexpected")), 1);
+        let json = serde_json::from_str::<u32>("x").unwrap_err();
+        assert_eq!(exit_code(&json.into()), 1);
+    }
+}
@@ -1,32 +1,45 @@
+use std::collections::HashSet;
 use std::fs::{create_dir_all, File};
-use std::io::{Read, Write};
+use std::io::{stdout, BufWriter, IsTerminal, Read, Write};
 use std::path::PathBuf;
-use std::str::FromStr;
 
 use arklib::id::ResourceId;
-use arklib::pdf::PDFQuality;
 use arklib::{app_id, provide_index};
 
 use chrono::prelude::DateTime;
-use chrono::Utc;
+use chrono::{TimeZone, Utc};
 
-use clap::Parser;
+use clap::{IntoApp, Parser};
 
 use fs_extra::dir::{self, CopyOptions};
 
 use home::home_dir;
 
-use crate::models::cli::{Command, FileCommand, Link, StorageCommand};
+use rayon::prelude::*;
+
+use crate::models::backup::Compression;
+use crate::models::cli::{
+    BackupCommand, Command, ConfigCommand, FileCommand, HistoryCommand,
+    IdCommand, IndexCommand, Link, MetadataCommand, PropertiesCommand,
+    RootsCommand, StorageCommand,
+};
 use crate::models::entry::EntryOutput;
 use crate::models::format::Format;
-use crate::models::sort::Sort;
-use crate::models::storage::{Storage, StorageType};
+use crate::models::kind::Kind;
+use crate::models::output::OutputFormat;
+use crate::models::sort::{Sort, SortKey};
+use crate::models::storage::{MergeStrategy, Storage, SyncPreference};
 
 use crate::error::AppError;
 
 use util::{
-    discover_roots, monitor_index, provide_root, read_storage_value,
-    storages_exists, timestamp, translate_storage,
+    compile_globs, confirm, discover_roots, glob_matches, human_size, is_quiet,
+    load_config, monitor_index, output_format, parse_time, progress_bar,
+    provide_backups_dir, provide_config, provide_root, read_content,
+    read_storage_value, reset_sigpipe, resolve_storage, roots_config,
+    save_config, set_config, set_default_root, set_quiet, storages_exists,
+    timestamp, translate_storage, truncate_preview, validate_date_format,
+    wait_for_changes, write_lines, write_table,
 };
 
 mod commands;
@@ -37,21 +50,77 @@ mod util;
 const ARK_CONFIG: &str = ".config/ark";
 const ARK_BACKUPS_PATH: &str = ".ark-backups";
 const ROOTS_CFG_FILENAME: &str = "roots";
+const CONFIG_FILENAME: &str = "config.toml";
+const DEFAULT_PREVIEW_LEN: usize = 100;
 
 struct StorageEntry {
+    root: Option<PathBuf>,
+    /// Relative to the canonical root, unless `--absolute` is given
     path: Option<PathBuf>,
     resource: Option<ResourceId>,
     content: Option<String>,
     tags: Option<Vec<String>>,
     scores: Option<u32>,
     datetime: Option<String>,
+    /// Missing when the file is gone since it was indexed
+    size: Option<u64>,
+    kind: Option<Kind>,
+    /// Value of `--metadata-key` in the extracted metadata
+    metadata: Option<String>,
+    /// Sorting must not depend on how `datetime` is formatted
+    modified: DateTime<Utc>,
 }
 
 #[tokio::main]
-async fn main() -> anyhow::Result<()> {
+async fn main() {
     env_logger::init();
+    reset_sigpipe();
 
     let args = models::cli::Cli::parse();
+    if let Some(root) = &args.default_root {
+        set_default_root(root.clone());
+    }
+    set_quiet(args.quiet);
+
+    if let Err(e) = run(args).await {
+        let code = error::exit_code(&e);
+        match e.downcast_ref::<AppError>() {
+            // Nobody is left to read the output, there is nothing to report
+            _ if code == error::BROKEN_PIPE_EXIT_CODE => {}
+            // Findings are the expected outcome of a check, not an error
+            Some(AppError::Findings(findings))
+            | Some(AppError::Mismatch(findings)) => eprintln!("{}", findings),
+            _ => eprintln!("Error: {}", e),
+        }
+
+        std::process::exit(code);
+    }
+}
+
+async fn run(args: models::cli::Cli) -> anyhow::Result<()> {
+    // Config commands must work even if the config is broken
+    if !matches!(args.command, Command::Config(_)) {
+        set_config(load_config()?);
+    }
+
+    // The script must not be mixed with any other output
+    if let Command::Completions { shell } = &args.command {
+        let mut app = models::cli::Cli::into_app();
+        clap_complete::generate(*shell, &mut app, "ark-cli", &mut stdout());
+        return Ok(());
+    }
+
+    if let Command::Man { output } = &args.command {
+        let app = models::cli::Cli::into_app();
+        match output {
+            Some(dir) => {
+                let written = commands::man::write_pages(app, dir)?;
+                eprintln!("Wrote {} pages to {}", written, dir.display());
+            }
+            None => commands::man::render_page(app, &mut stdout())?,
+        }
+        return Ok(());
+    }
 
     let app_id_dir = home_dir().ok_or(AppError::HomeDirNotFound)?;
 
@@ -62,7 +131,9 @@ async fn main() -> anyhow::Result<()> {
             .map_err(|e| AppError::ArkDirectoryCreationError(e.to_string()))?;
     }
 
-    println!("Loading app id at {}...", ark_dir.display());
+    if !is_quiet() {
+        eprintln!("Loading app id at {}...", ark_dir.display());
+    }
 
     let _ = app_id::load(ark_dir)
         .map_err(|e| AppError::AppIdLoadError(e.to_string()))?;
@@ -79,9 +150,65 @@ async fn main() -> anyhow::Result<()> {
             tags,
             scores,
             sort,
+            sort_by,
             filter,
+            all_roots,
+            format,
+            preview_len,
+            limit,
+            offset,
+            min_score,
+            max_score,
+            since,
+            until,
+            path_glob,
+            exclude_glob,
+            date_format,
+            iso,
+            short_ids,
+            print0,
+            size,
+            bytes,
+            kind,
+            filter_kind,
+            watch,
+            interval,
+            metadata_key,
+            absolute,
+            // The default, only spelled out by scripts
+            relative: _,
         } => {
-            let root = provide_root(root_dir)?;
+            if let Some(format) = date_format {
+                validate_date_format(format)?;
+            }
+            if *sort_by == Some(SortKey::Metadata) && metadata_key.is_none() {
+                return Err(AppError::InvalidOptions(
+                    "sorting by metadata needs --metadata-key".to_owned(),
+                )
+                .into());
+            }
+            let path_globs = compile_globs(path_glob)?;
+            let exclude_globs = compile_globs(exclude_glob)?;
+            let since = since.as_deref().map(parse_time).transpose()?;
+            let until = until.as_deref().map(parse_time).transpose()?;
+
+            let roots = if *all_roots {
+                discover_roots(&None)?
+                    .into_iter()
+                    .filter(|root| {
+                        let exists = root.is_dir();
+                        if !exists {
+                            eprintln!(
+                                "Skipping missing root {}",
+                                root.display()
+                            );
+                        }
+                        exists
+                    })
+                    .collect()
+            } else {
+                vec![provide_root(root_dir)?]
+            };
 
             let entry_output = match (entry, entry_id, entry_path, entry_link) {
                 (Some(e), false, false, false) => Ok(*e),
@@ -93,285 +220,800 @@ async fn main() -> anyhow::Result<()> {
                 _ => Err(AppError::InvalidEntryOption),
             }?;
 
-            let mut storage_entries: Vec<StorageEntry> = provide_index(&root)
-                .map_err(|_| {
-                    AppError::IndexError("Could not provide index".to_owned())
-                })?
-                .read()
-                .map_err(|_| {
-                    AppError::IndexError("Could not read index".to_owned())
-                })?
-                .path2id
-                .iter()
-                .filter_map(|(path, resource)| {
-                    let tags = if *tags {
-                        Some(
-                            read_storage_value(
-                                &root,
-                                "tags",
-                                &resource.id.to_string(),
-                                &None,
-                            )
-                            .map_or(vec![], |s| {
-                                s.split(',')
-                                    .map(|s| s.trim().to_string())
-                                    .collect::<Vec<_>>()
-                            }),
-                        )
-                    } else {
-                        None
-                    };
-
-                    let scores = if *scores {
-                        Some(
-                            read_storage_value(
-                                &root,
-                                "scores",
-                                &resource.id.to_string(),
-                                &None,
-                            )
-                            .map_or(0, |s| s.parse::<u32>().unwrap_or(0)),
+            let print_listing = || -> anyhow::Result<()> {
+                let mut storage_entries: Vec<StorageEntry> = Vec::new();
+                let mut indexed_ids = Vec::new();
+                for root in &roots {
+                    let canonical_root = root.canonicalize()?;
+                    let index = provide_index(root).map_err(|_| {
+                        AppError::IndexError(
+                            "Could not provide index".to_owned(),
                         )
-                    } else {
-                        None
-                    };
+                    })?;
+                    let index = index.read().map_err(|_| {
+                        AppError::IndexError("Could not read index".to_owned())
+                    })?;
+                    indexed_ids.extend(index.id2path.keys().copied());
+
+                    // Tags and scores are read from disk for every entry,
+                    // so the entries are built in parallel
+                    let entries = index
+                        .path2id
+                        .par_iter()
+                        .filter_map(|(path, resource)| {
+                            // Matched against the indexed path, whatever is shown
+                            let relative = path
+                                .as_path()
+                                .strip_prefix(&canonical_root)
+                                .unwrap_or(path.as_path());
+                            if (!path_globs.is_empty()
+                                && !glob_matches(&path_globs, relative))
+                                || glob_matches(&exclude_globs, relative)
+                            {
+                                return None;
+                            }
 
-                    let datetime = if *modified {
-                        let format = "%b %e %H:%M %Y";
-                        Some(
-                            DateTime::<Utc>::from(resource.modified)
-                                .format(format)
-                                .to_string(),
-                        )
-                    } else {
-                        None
-                    };
+                            let tags = if *tags {
+                                Some(
+                                    read_storage_value(
+                                        root,
+                                        "tags",
+                                        &resource.id.to_string(),
+                                        &None,
+                                    )
+                                    .map_or(vec![], |s| {
+                                        s.split(',')
+                                            .map(|s| s.trim().to_string())
+                                            .collect::<Vec<_>>()
+                                    }),
+                                )
+                            } else {
+                                None
+                            };
+
+                            let score_range =
+                                min_score.is_some() || max_score.is_some();
+                            let score = if *scores || score_range {
+                                Some(
+                                    read_storage_value(
+                                        root,
+                                        "scores",
+                                        &resource.id.to_string(),
+                                        &None,
+                                    )
+                                    .map_or(0, |s| {
+                                        s.parse::<u32>().unwrap_or(0)
+                                    }),
+                                )
+                            } else {
+                                None
+                            };
+
+                            let modified_at =
+                                DateTime::<Utc>::from(resource.modified);
+                            if since.is_some_and(|since| modified_at < since)
+                                || until
+                                    .is_some_and(|until| modified_at > until)
+                            {
+                                return None;
+                            }
 
-                    let (path, resource, content) = match entry_output {
-                        EntryOutput::Both => (
-                            Some(path.to_owned().into_path_buf()),
-                            Some(resource.id),
-                            None,
-                        ),
-                        EntryOutput::Path => {
-                            (Some(path.to_owned().into_path_buf()), None, None)
-                        }
-                        EntryOutput::Id => (None, Some(resource.id), None),
-                        EntryOutput::Link => match File::open(&path) {
-                            Ok(mut file) => {
-                                let mut contents = String::new();
-                                match file.read_to_string(&mut contents) {
-                                    Ok(_) => (None, None, Some(contents)),
-                                    Err(_) => return None,
+                            // Resources without a score count as 0
+                            if let Some(score) = score {
+                                if min_score.is_some_and(|min| score < min)
+                                    || max_score.is_some_and(|max| score > max)
+                                {
+                                    return None;
                                 }
                             }
-                            Err(_) => return None,
-                        },
-                    };
+                            let scores = if *scores {
+                                score
+                            } else {
+                                None
+                            };
+
+                            let datetime = if *modified && *iso {
+                                Some(modified_at.to_rfc3339())
+                            } else if *modified {
+                                let format = date_format
+                                    .as_deref()
+                                    .unwrap_or("%b %e %H:%M %Y");
+                                Some(modified_at.format(format).to_string())
+                            } else {
+                                None
+                            };
+
+                            let file_kind = (*kind || filter_kind.is_some())
+                                .then(|| Kind::of(path.as_path()));
+                            if filter_kind.is_some()
+                                && file_kind != *filter_kind
+                            {
+                                return None;
+                            }
 
-                    Some(StorageEntry {
-                        path,
-                        resource,
-                        content,
-                        tags,
-                        scores,
-                        datetime,
-                    })
-                })
-                .collect::<Vec<_>>();
+                            let file_size =
+                                if *size || *sort_by == Some(SortKey::Size) {
+                                    std::fs::metadata(path)
+                                        .ok()
+                                        .map(|metadata| metadata.len())
+                                } else {
+                                    None
+                                };
+
+                            let metadata =
+                                metadata_key.as_ref().and_then(|key| {
+                                    read_storage_value(
+                                        root,
+                                        "metadata",
+                                        &resource.id.to_string(),
+                                        &None,
+                                    )
+                                    .ok()
+                                    .and_then(|value| {
+                                        commands::metadata::metadata_value(
+                                            &value, key,
+                                        )
+                                    })
+                                });
+
+                            let shown_path = if *absolute {
+                                path.to_owned().into_path_buf()
+                            } else {
+                                relative.to_path_buf()
+                            };
+                            let (path, resource, content) = match entry_output {
+                                EntryOutput::Both => {
+                                    (Some(shown_path), Some(resource.id), None)
+                                }
+                                EntryOutput::Path => {
+                                    (Some(shown_path), None, None)
+                                }
+                                EntryOutput::Id => {
+                                    (None, Some(resource.id), None)
+                                }
+                                EntryOutput::Link => match File::open(&path) {
+                                    Ok(mut file) => {
+                                        let mut bytes = Vec::new();
+                                        if file.read_to_end(&mut bytes).is_err()
+                                        {
+                                            return None;
+                                        }
+
+                                        let contents =
+                                            match String::from_utf8(bytes) {
+                                                Ok(contents) => contents,
+                                                Err(e) => format!(
+                                                    "<binary, {} bytes>",
+                                                    e.as_bytes().len()
+                                                ),
+                                            };
+                                        (None, None, Some(contents))
+                                    }
+                                    Err(_) => return None,
+                                },
+                            };
+
+                            Some(StorageEntry {
+                                root: all_roots.then(|| root.clone()),
+                                path,
+                                resource,
+                                content,
+                                tags,
+                                scores,
+                                datetime,
+                                size: file_size,
+                                kind: file_kind,
+                                metadata,
+                                modified: modified_at,
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    storage_entries.extend(entries);
+                }
 
-            match sort {
-                Some(Sort::Asc) => {
-                    storage_entries.sort_by(|a, b| a.datetime.cmp(&b.datetime))
+                let short_len = short_ids.then(|| {
+                    let floor = provide_config()
+                        .short_id_min_len
+                        .unwrap_or(commands::id::MIN_SHORT_ID_LEN);
+                    commands::id::short_id_len(&indexed_ids, floor)
+                });
+                let id_text = |id: &ResourceId| match short_len {
+                    Some(len) => commands::id::shorten(id, len),
+                    None => id.to_string(),
+                };
+
+                // Sorting by a key alone sorts in ascending order
+                let sort = match (sort, sort_by) {
+                    (None, Some(_)) => Some(&Sort::Asc),
+                    (sort, _) => sort.as_ref(),
+                };
+                let by_key = |a: &StorageEntry, b: &StorageEntry| match sort_by
+                {
+                    Some(SortKey::Size) => a.size.cmp(&b.size),
+                    Some(SortKey::Metadata) => a.metadata.cmp(&b.metadata),
+                    _ => a.modified.cmp(&b.modified),
+                };
+                // Entries are collected in hash order, ties and unsorted
+                // listings fall back to the path, so that pages are stable
+                let by_path = |a: &StorageEntry, b: &StorageEntry| {
+                    (&a.root, &a.path, &a.resource).cmp(&(
+                        &b.root,
+                        &b.path,
+                        &b.resource,
+                    ))
+                };
+                match sort {
+                    Some(Sort::Asc) => storage_entries.sort_by(|a, b| {
+                        by_key(a, b).then_with(|| by_path(a, b))
+                    }),
+                    Some(Sort::Desc) => storage_entries.sort_by(|a, b| {
+                        by_key(b, a).then_with(|| by_path(a, b))
+                    }),
+                    None => storage_entries.sort_by(by_path),
+                };
+
+                if let Some(filter) = filter {
+                    storage_entries.retain(|entry| {
+                        entry
+                            .tags
+                            .as_ref()
+                            .map(|tags| tags.contains(filter))
+                            .unwrap_or(false)
+                    });
                 }
 
-                Some(Sort::Desc) => {
-                    storage_entries.sort_by(|a, b| b.datetime.cmp(&a.datetime))
+                // Pages are cut after sorting and filtering to stay stable
+                let offset = offset.unwrap_or(0);
+                let limit = limit.unwrap_or(usize::MAX);
+                let mut storage_entries = storage_entries
+                    .into_iter()
+                    .skip(offset)
+                    .take(limit)
+                    .collect::<Vec<_>>();
+
+                if let Some(OutputFormat::Json) = output_format(format) {
+                    let to_json = |entry: &StorageEntry| {
+                        let mut output = serde_json::json!({});
+                        if let Some(content) = &entry.content {
+                            output["link"] = content.trim().into();
+                        }
+                        if let Some(path) = &entry.path {
+                            output["path"] = path.display().to_string().into();
+                        }
+                        if let Some(resource) = &entry.resource {
+                            output["id"] = resource.to_string().into();
+                            if short_len.is_some() {
+                                output["short_id"] = id_text(resource).into();
+                            }
+                        }
+                        if let Some(tags) = &entry.tags {
+                            output["tags"] = tags.clone().into();
+                        }
+                        if let Some(scores) = &entry.scores {
+                            output["score"] = (*scores).into();
+                        }
+                        if let Some(datetime) = &entry.datetime {
+                            output["modified"] = datetime.clone().into();
+                        }
+                        if *size {
+                            output["size"] = entry.size.into();
+                        }
+                        if let (true, Some(kind)) = (*kind, &entry.kind) {
+                            output["kind"] = kind.to_string().into();
+                        }
+                        if let Some(key) = metadata_key {
+                            output[key.as_str()] =
+                                entry.metadata.clone().into();
+                        }
+                        output
+                    };
+
+                    let output = if *all_roots {
+                        roots
+                            .iter()
+                            .map(|root| {
+                                serde_json::json!({
+                                    "root": root.display().to_string(),
+                                    "entries": storage_entries
+                                        .iter()
+                                        .filter(|entry| {
+                                            entry.root.as_ref() == Some(root)
+                                        })
+                                        .map(to_json)
+                                        .collect::<Vec<_>>(),
+                                })
+                            })
+                            .collect::<Vec<_>>()
+                    } else {
+                        storage_entries
+                            .iter()
+                            .map(to_json)
+                            .collect::<Vec<_>>()
+                    };
+                    println!("{}", serde_json::Value::Array(output));
+                    return Ok(());
                 }
-                None => (),
-            };
 
-            if let Some(filter) = filter {
-                storage_entries.retain(|entry| {
-                    entry
-                        .tags
-                        .as_ref()
-                        .map(|tags| tags.contains(filter))
-                        .unwrap_or(false)
-                });
-            }
+                let columns = [
+                    ("root", *all_roots),
+                    ("link", entry_output == EntryOutput::Link),
+                    (
+                        "path",
+                        matches!(
+                            entry_output,
+                            EntryOutput::Path | EntryOutput::Both
+                        ),
+                    ),
+                    (
+                        "id",
+                        matches!(
+                            entry_output,
+                            EntryOutput::Id | EntryOutput::Both
+                        ),
+                    ),
+                    ("tags", *tags),
+                    ("score", *scores),
+                    ("modified", *modified),
+                    ("size", *size),
+                    ("kind", *kind),
+                    ("metadata", metadata_key.is_some()),
+                ]
+                .into_iter()
+                .filter(|(_, shown)| *shown)
+                .map(|(name, _)| name)
+                .collect::<Vec<_>>();
 
-            let no_tags = "NO_TAGS";
-            let no_scores = "NO_SCORE";
+                let size_text = |size: Option<u64>| match size {
+                    Some(size) if *bytes => size.to_string(),
+                    Some(size) => human_size(size),
+                    None => "N/A".to_owned(),
+                };
+                let cell = |entry: &StorageEntry, column: &str| match column {
+                    "root" => entry
+                        .root
+                        .as_ref()
+                        .map(|root| root.display().to_string()),
+                    "link" => entry.content.clone(),
+                    "path" => entry
+                        .path
+                        .as_ref()
+                        .map(|path| path.display().to_string()),
+                    "id" => entry.resource.as_ref().map(id_text),
+                    "tags" => entry.tags.as_ref().map(|tags| tags.join(";")),
+                    "score" => entry.scores.map(|score| score.to_string()),
+                    "size" => Some(size_text(entry.size)),
+                    "kind" => entry.kind.map(|kind| kind.to_string()),
+                    "metadata" => entry.metadata.clone(),
+                    _ => entry.datetime.clone(),
+                };
+
+                if *print0 {
+                    if columns.len() != 1 {
+                        return Err(AppError::InvalidOptions(format!(
+                            "--print0 needs a single column, but {} are shown: {}",
+                            columns.len(),
+                            columns.join(", ")
+                        ))
+                        .into());
+                    }
 
-            let longest_path = storage_entries
-                .iter()
-                .map(|entry| {
-                    if let Some(path) = entry.path.as_ref() {
-                        path.display().to_string().len()
-                    } else {
-                        0
+                    let mut stdout = stdout().lock();
+                    for entry in &storage_entries {
+                        let value = cell(entry, columns[0]).unwrap_or_default();
+                        stdout.write_all(value.as_bytes())?;
+                        stdout.write_all(b"\0")?;
                     }
-                })
-                .max_by(|a, b| a.cmp(b))
-                .unwrap_or(0);
+                    stdout.flush()?;
+                    return Ok(());
+                }
 
-            let longest_id = storage_entries.iter().fold(0, |acc, entry| {
-                if let Some(resource) = &entry.resource {
-                    let id_len = resource.to_string().len();
-                    if id_len > acc {
-                        id_len
-                    } else {
-                        acc
+                let delimiter = match output_format(format) {
+                    Some(OutputFormat::Csv) => Some(b','),
+                    Some(OutputFormat::Tsv) => Some(b'\t'),
+                    _ => None,
+                };
+                if let Some(delimiter) = delimiter {
+                    let rows = storage_entries.iter().map(|entry| {
+                        columns
+                            .iter()
+                            .map(|column| {
+                                cell(entry, column).unwrap_or_default()
+                            })
+                            .collect()
+                    });
+                    write_table(stdout(), delimiter, &columns, rows)?;
+                    return Ok(());
+                }
+
+                let preview_len = preview_len.unwrap_or(DEFAULT_PREVIEW_LEN);
+                for entry in &mut storage_entries {
+                    if let Some(content) = entry.content.as_mut() {
+                        *content = truncate_preview(content, preview_len);
                     }
-                } else {
-                    acc
                 }
-            });
 
-            let longest_tags = storage_entries.iter().fold(0, |acc, entry| {
-                let tags_len = entry
-                    .tags
-                    .as_ref()
-                    .map(|tags| {
-                        if tags.is_empty() {
-                            no_tags.len()
+                let no_tags = "NO_TAGS";
+                let no_scores = "NO_SCORE";
+
+                let longest_root = storage_entries
+                    .iter()
+                    .map(|entry| {
+                        entry
+                            .root
+                            .as_ref()
+                            .map(|root| root.display().to_string().len())
+                            .unwrap_or(0)
+                    })
+                    .max()
+                    .unwrap_or(0);
+
+                let longest_path = storage_entries
+                    .iter()
+                    .map(|entry| {
+                        if let Some(path) = entry.path.as_ref() {
+                            path.display().to_string().len()
                         } else {
-                            tags.join(", ").len()
+                            0
                         }
                     })
+                    .max_by(|a, b| a.cmp(b))
                     .unwrap_or(0);
-                if tags_len > acc {
-                    tags_len
-                } else {
-                    acc
-                }
-            });
 
-            let longest_scores =
-                storage_entries.iter().fold(0, |acc, entry| {
-                    let scores_len = entry
-                        .scores
-                        .as_ref()
-                        .map(|score| {
-                            if *score == 0 {
-                                no_scores.len()
+                let longest_id =
+                    storage_entries.iter().fold(0, |acc, entry| {
+                        if let Some(resource) = &entry.resource {
+                            let id_len = id_text(resource).len();
+                            if id_len > acc {
+                                id_len
                             } else {
-                                score.to_string().len()
+                                acc
                             }
-                        })
-                        .unwrap_or(0);
-                    if scores_len > acc {
-                        scores_len
-                    } else {
-                        acc
+                        } else {
+                            acc
+                        }
+                    });
+
+                let longest_tags =
+                    storage_entries.iter().fold(0, |acc, entry| {
+                        let tags_len = entry
+                            .tags
+                            .as_ref()
+                            .map(|tags| {
+                                if tags.is_empty() {
+                                    no_tags.len()
+                                } else {
+                                    tags.join(", ").len()
+                                }
+                            })
+                            .unwrap_or(0);
+                        if tags_len > acc {
+                            tags_len
+                        } else {
+                            acc
+                        }
+                    });
+
+                let longest_scores =
+                    storage_entries.iter().fold(0, |acc, entry| {
+                        let scores_len = entry
+                            .scores
+                            .as_ref()
+                            .map(|score| {
+                                if *score == 0 {
+                                    no_scores.len()
+                                } else {
+                                    score.to_string().len()
+                                }
+                            })
+                            .unwrap_or(0);
+                        if scores_len > acc {
+                            scores_len
+                        } else {
+                            acc
+                        }
+                    });
+
+                let longest_datetime =
+                    storage_entries.iter().fold(0, |acc, entry| {
+                        let datetime_len = entry
+                            .datetime
+                            .as_ref()
+                            .map(|datetime| datetime.len())
+                            .unwrap_or(0);
+                        if datetime_len > acc {
+                            datetime_len
+                        } else {
+                            acc
+                        }
+                    });
+
+                let longest_size = storage_entries
+                    .iter()
+                    .map(|entry| size_text(entry.size).len())
+                    .max()
+                    .unwrap_or(0);
+
+                let longest_kind = storage_entries
+                    .iter()
+                    .filter_map(|entry| entry.kind)
+                    .map(|kind| kind.to_string().len())
+                    .max()
+                    .unwrap_or(0);
+
+                let longest_metadata = storage_entries
+                    .iter()
+                    .filter_map(|entry| entry.metadata.as_ref())
+                    .map(|metadata| metadata.chars().count())
+                    .max()
+                    .unwrap_or(0);
+
+                let longest_content =
+                    storage_entries.iter().fold(0, |acc, entry| {
+                        let content_len = entry
+                            .content
+                            .as_ref()
+                            .map(|content| content.chars().count())
+                            .unwrap_or(0);
+                        if content_len > acc {
+                            content_len
+                        } else {
+                            acc
+                        }
+                    });
+
+                let lines = storage_entries.iter().map(|entry| {
+                    let mut output = String::new();
+
+                    if let Some(root) = &entry.root {
+                        output.push_str(&format!(
+                            "{:width$} ",
+                            root.display(),
+                            width = longest_root
+                        ));
                     }
-                });
 
-            let longest_datetime =
-                storage_entries.iter().fold(0, |acc, entry| {
-                    let datetime_len = entry
-                        .datetime
-                        .as_ref()
-                        .map(|datetime| datetime.len())
-                        .unwrap_or(0);
-                    if datetime_len > acc {
-                        datetime_len
-                    } else {
-                        acc
+                    if let Some(content) = &entry.content {
+                        output.push_str(&format!(
+                            "{:width$} ",
+                            content,
+                            width = longest_content
+                        ));
                     }
-                });
 
-            let longest_content =
-                storage_entries.iter().fold(0, |acc, entry| {
-                    let content_len = entry
-                        .content
-                        .as_ref()
-                        .map(|content| content.len())
-                        .unwrap_or(0);
-                    if content_len > acc {
-                        content_len
-                    } else {
-                        acc
+                    if let Some(path) = &entry.path {
+                        output.push_str(&format!(
+                            "{:width$} ",
+                            path.display(),
+                            width = longest_path
+                        ));
                     }
-                });
 
-            for entry in &storage_entries {
-                let mut output = String::new();
+                    if let Some(resource) = &entry.resource {
+                        output.push_str(&format!(
+                            "{:width$} ",
+                            id_text(resource),
+                            width = longest_id
+                        ));
+                    }
 
-                if let Some(content) = &entry.content {
-                    output.push_str(&format!(
-                        "{:width$} ",
-                        content,
-                        width = longest_content
-                    ));
-                }
+                    if let Some(tags) = &entry.tags {
+                        let tags_out = if tags.is_empty() {
+                            no_tags.to_owned()
+                        } else {
+                            tags.join(", ")
+                        };
+
+                        output.push_str(&format!(
+                            "{:width$} ",
+                            tags_out,
+                            width = longest_tags
+                        ));
+                    }
 
-                if let Some(path) = &entry.path {
-                    output.push_str(&format!(
-                        "{:width$} ",
-                        path.display(),
-                        width = longest_path
-                    ));
-                }
+                    if let Some(scores) = &entry.scores {
+                        let scores_out = if *scores == 0 {
+                            no_scores.to_owned()
+                        } else {
+                            scores.to_string()
+                        };
+
+                        output.push_str(&format!(
+                            "{:width$} ",
+                            scores_out,
+                            width = longest_scores
+                        ));
+                    }
 
-                if let Some(resource) = &entry.resource {
-                    output.push_str(&format!(
-                        "{:width$} ",
-                        resource.to_string(),
-                        width = longest_id
-                    ));
-                }
+                    if let Some(datetime) = &entry.datetime {
+                        output.push_str(&format!(
+                            "{:width$} ",
+                            datetime,
+                            width = longest_datetime
+                        ));
+                    }
 
-                if let Some(tags) = &entry.tags {
-                    let tags_out = if tags.is_empty() {
-                        no_tags.to_owned()
-                    } else {
-                        tags.join(", ")
-                    };
+                    if *size {
+                        output.push_str(&format!(
+                            "{:>width$} ",
+                            size_text(entry.size),
+                            width = longest_size
+                        ));
+                    }
 
-                    output.push_str(&format!(
-                        "{:width$} ",
-                        tags_out,
-                        width = longest_tags
-                    ));
-                }
+                    if let (true, Some(kind)) = (*kind, &entry.kind) {
+                        output.push_str(&format!(
+                            "{:width$} ",
+                            kind.to_string(),
+                            width = longest_kind
+                        ));
+                    }
 
-                if let Some(scores) = &entry.scores {
-                    let scores_out = if *scores == 0 {
-                        no_scores.to_owned()
-                    } else {
-                        scores.to_string()
-                    };
+                    if metadata_key.is_some() {
+                        output.push_str(&format!(
+                            "{:width$} ",
+                            entry.metadata.as_deref().unwrap_or("N/A"),
+                            width = longest_metadata
+                        ));
+                    }
 
-                    output.push_str(&format!(
-                        "{:width$} ",
-                        scores_out,
-                        width = longest_scores
-                    ));
-                }
+                    output
+                });
+                write_lines(&mut stdout().lock(), lines)?;
+                Ok(())
+            };
 
-                if let Some(datetime) = &entry.datetime {
-                    output.push_str(&format!(
-                        "{:width$} ",
-                        datetime,
-                        width = longest_datetime
-                    ));
+            print_listing()?;
+            if *watch {
+                let millis = interval
+                    .or(provide_config().monitor_interval)
+                    .unwrap_or(1000);
+                let redraw = stdout().is_terminal()
+                    && output_format(format).is_none()
+                    && !print0;
+                loop {
+                    wait_for_changes(&roots, millis)?;
+                    if redraw {
+                        // Clears the terminal and moves to its top
+                        print!("\x1B[2J\x1B[H");
+                    }
+                    print_listing()?;
                 }
+            }
+        }
+        Command::Backup {
+            command: Some(BackupCommand::List { output, format }),
+            ..
+        } => {
+            let backups_dir = provide_backups_dir(output)?;
+            let backups = commands::backup::list_backups(&backups_dir);
+
+            let describe = |backup: &commands::backup::BackupDir| {
+                let time = Utc
+                    .timestamp_opt(backup.timestamp as i64, 0)
+                    .single()
+                    .map(|time| time.to_rfc3339())
+                    .unwrap_or_else(|| backup.timestamp.to_string());
+                let roots = commands::backup::backup_roots(backup)
+                    .into_iter()
+                    .map(|root| {
+                        let exists = root.is_dir();
+                        (root, exists)
+                    })
+                    .collect::<Vec<_>>();
+                let size = commands::backup::backup_size(backup);
+                (time, roots, size)
+            };
 
-                println!("{}", output);
+            if let Some(OutputFormat::Json) = output_format(format) {
+                let output = backups
+                    .iter()
+                    .map(|backup| {
+                        let (time, roots, size) = describe(backup);
+                        serde_json::json!({
+                            "timestamp": backup.timestamp,
+                            "time": time,
+                            "path": backup.path.display().to_string(),
+                            "size": size,
+                            "valid": backup.valid,
+                            "roots": roots
+                                .iter()
+                                .map(|(root, exists)| serde_json::json!({
+                                    "path": root.display().to_string(),
+                                    "exists": exists,
+                                }))
+                                .collect::<Vec<_>>(),
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                println!("{}", serde_json::Value::Array(output));
+            } else {
+                for backup in &backups {
+                    let (time, roots, size) = describe(backup);
+                    let status = if backup.valid {
+                        ""
+                    } else {
+                        " (partial: roots file is missing)"
+                    };
+                    println!("{} {} bytes{}", time, size, status);
+                    println!("\t{}", backup.path.display());
+                    for (root, exists) in roots {
+                        let missing = if exists {
+                            ""
+                        } else {
+                            " (missing)"
+                        };
+                        println!("\t\t{}{}", root.display(), missing);
+                    }
+                }
             }
         }
-        Command::Backup { roots_cfg } => {
+        Command::Backup {
+            command:
+                Some(BackupCommand::Prune {
+                    keep_last,
+                    keep_days,
+                    yes,
+                    dry_run,
+                    output,
+                }),
+            ..
+        } => {
+            let backups_dir = provide_backups_dir(output)?;
+
+            commands::backup::prune_backups(
+                &backups_dir,
+                *keep_last,
+                *keep_days,
+                *yes,
+                *dry_run,
+            )?;
+        }
+        Command::Backup {
+            command:
+                Some(BackupCommand::Restore {
+                    timestamp,
+                    yes,
+                    dry_run,
+                    output,
+                }),
+            ..
+        } => {
+            let backups_dir = provide_backups_dir(output)?;
+            let backup = commands::backup::list_backups(&backups_dir)
+                .into_iter()
+                .find(|backup| backup.timestamp == *timestamp)
+                .ok_or_else(|| {
+                    AppError::InvalidOptions(format!(
+                        "No backup made at {} in {}",
+                        timestamp,
+                        backups_dir.display()
+                    ))
+                })?;
+
+            commands::backup::restore_backup(&backup, *yes, *dry_run)?;
+        }
+        Command::Backup {
+            command: None,
+            roots_cfg,
+            full,
+            archive,
+            compression,
+            output,
+        } => {
             let timestamp = timestamp().as_secs();
-            let backup_dir = home_dir()
-                .ok_or(AppError::HomeDirNotFound)?
-                .join(ARK_BACKUPS_PATH)
-                .join(timestamp.to_string());
+            let backups_dir = provide_backups_dir(output)?;
+            let backup_dir = backups_dir.join(timestamp.to_string());
+
+            let compression = compression.unwrap_or(Compression::Gzip);
+            let archive_path = backups_dir.join(format!(
+                "backup-{}.{}",
+                timestamp,
+                compression.extension()
+            ));
 
-            if backup_dir.is_dir() {
+            if backup_dir.is_dir() || archive_path.exists() {
                 println!("Wait at least 1 second, please!");
-                std::process::exit(0)
+                return Ok(());
             }
 
             println!("Preparing backup:");
@@ -390,7 +1032,21 @@ async fn main() -> anyhow::Result<()> {
 
             if valid.is_empty() {
                 println!("Nothing to backup. Bye!");
-                std::process::exit(0)
+                return Ok(());
+            }
+
+            if *archive {
+                create_dir_all(&backups_dir)?;
+
+                println!("Performing backups:");
+                commands::backup::create_archive(
+                    &archive_path,
+                    &valid,
+                    compression,
+                )?;
+
+                println!("Backup created:\n\t{}", archive_path.display());
+                return Ok(());
             }
 
             create_dir_all(&backup_dir).map_err(|_| {
@@ -405,132 +1061,861 @@ async fn main() -> anyhow::Result<()> {
             valid.iter().for_each(|root| {
                 let res = writeln!(roots_cfg_backup, "{}", root.display());
                 if let Err(e) = res {
-                    println!("Failed to write root to backup file: {}", e);
+                    eprintln!("Failed to write root to backup file: {}", e);
                 }
             });
 
             println!("Performing backups:");
-            valid
-                .into_iter()
-                .enumerate()
-                .for_each(|(i, root)| {
-                    println!("\tRoot {}", root.display());
-                    let storage_backup = backup_dir.join(i.to_string());
-
-                    let mut options = CopyOptions::new();
-                    options.overwrite = true;
-                    options.copy_inside = true;
-
-                    let result = dir::copy(
-                        root.join(arklib::ARK_FOLDER),
-                        storage_backup,
-                        &options,
-                    );
-
-                    if let Err(e) = result {
-                        println!("\t\tFailed to copy storages!\n\t\t{}", e);
+            let mut saved = 0;
+            for (i, root) in valid.into_iter().enumerate() {
+                println!("\tRoot {}", root.display());
+                let storage_backup = backup_dir.join(i.to_string());
+                let storages = root.join(arklib::ARK_FOLDER);
+                let progress = progress_bar(
+                    "Copying storages",
+                    dir::get_size(&storages).ok(),
+                );
+
+                let previous = if *full {
+                    None
+                } else {
+                    commands::backup::previous_backup(
+                        &backups_dir,
+                        timestamp,
+                        &root,
+                    )
+                };
+
+                let result = match previous {
+                    Some(previous) => commands::backup::copy_incremental(
+                        &storages,
+                        &storage_backup,
+                        &previous,
+                        &progress,
+                    )
+                    .map(|bytes| saved += bytes),
+                    None => {
+                        let mut options = CopyOptions::new();
+                        options.overwrite = true;
+                        options.copy_inside = true;
+
+                        dir::copy_with_progress(
+                            &storages,
+                            storage_backup,
+                            &options,
+                            |transit| {
+                                progress.set_position(transit.copied_bytes);
+                                dir::TransitProcessResult::ContinueOrAbort
+                            },
+                        )
+                        .map(|_| ())
+                        .map_err(|e| {
+                            AppError::BackupCreationError(e.to_string())
+                        })
                     }
-                });
+                };
+
+                progress.finish_and_clear();
+                if let Err(e) = result {
+                    eprintln!("\t\tFailed to copy storages!\n\t\t{}", e);
+                }
+            }
 
             println!("Backup created:\n\t{}", backup_dir.display());
+            if saved > 0 {
+                println!(
+                    "Unchanged files were hard-linked, {} bytes saved",
+                    saved
+                );
+            }
         }
-        Command::Collisions { root_dir } => monitor_index(root_dir, None)?,
-        Command::Monitor { root_dir, interval } => {
-            let millis = interval.unwrap_or(1000);
-            monitor_index(root_dir, Some(millis))?
-        }
-        Command::Render { path, quality } => {
-            let filepath = path.to_owned().unwrap();
-            let quality = match quality.to_owned().unwrap().as_str() {
-                "high" => Ok(PDFQuality::High),
-                "medium" => Ok(PDFQuality::Medium),
-                "low" => Ok(PDFQuality::Low),
-                _ => Err(AppError::InvalidRenderOption),
-            }?;
-            let buf = File::open(&filepath).unwrap();
-            let dest_path = filepath.with_file_name(
-                filepath
-                    .file_stem()
-                    .unwrap()
-                    .to_str()
-                    .unwrap()
-                    .to_owned()
-                    + ".png",
-            );
-            let img = arklib::pdf::render_preview_page(buf, quality);
-            img.save(dest_path).unwrap();
-        }
-        Command::Link(link) => match &link {
-            Link::Create {
-                root_dir,
-                url,
-                title,
-                desc,
-            } => {
-                let root = provide_root(root_dir)?;
-                let url = url.as_ref().ok_or_else(|| {
-                    AppError::LinkCreationError(
-                        "Url was not provided".to_owned(),
-                    )
-                })?;
-                let title = title.as_ref().ok_or_else(|| {
-                    AppError::LinkCreationError(
-                        "Title was not provided".to_owned(),
-                    )
-                })?;
+        Command::Collisions {
+            root_dir,
+            format,
+            resolve,
+            short_ids,
+        } => {
+            let root = provide_root(root_dir)?;
+            let collisions = commands::collisions::find_collisions(&root);
+
+            let short_len = short_ids.then(|| {
+                let ids = util::provide_index(&root)
+                    .id2path
+                    .keys()
+                    .copied()
+                    .collect::<Vec<_>>();
+                let floor = provide_config()
+                    .short_id_min_len
+                    .unwrap_or(commands::id::MIN_SHORT_ID_LEN);
+                commands::id::short_id_len(&ids, floor)
+            });
 
-                println!("Saving link...");
+            if let Some(OutputFormat::Json) = output_format(format) {
+                let output = collisions
+                    .iter()
+                    .map(|(id, paths)| {
+                        let mut output = serde_json::json!({
+                            "id": id.to_string(),
+                            "paths": paths
+                                .iter()
+                                .map(|path| path.display().to_string())
+                                .collect::<Vec<_>>(),
+                        });
+                        if let Some(len) = short_len {
+                            output["short_id"] =
+                                commands::id::shorten(id, len).into();
+                        }
+                        output
+                    })
+                    .collect::<Vec<_>>();
+                println!("{}", serde_json::Value::Array(output));
+            } else {
+                for (id, paths) in &collisions {
+                    match short_len {
+                        Some(len) => {
+                            println!("{}", commands::id::shorten(id, len))
+                        }
+                        None => println!("{}", id),
+                    }
+                    for path in paths {
+                        println!("\t{}", path.display());
+                    }
+                }
+            }
+
+            let remaining = match resolve {
+                Some(resolution) => {
+                    let resolved = commands::collisions::resolve_collisions(
+                        &root,
+                        &collisions,
+                        *resolution,
+                    )?;
+                    for (from, to) in &resolved.moved {
+                        println!(
+                            "Moved {} to {}",
+                            from.display(),
+                            to.display()
+                        );
+                    }
+                    resolved.unresolved.len()
+                }
+                None => collisions.len(),
+            };
 
-                match commands::link::create_link(
+            if remaining > 0 {
+                return Err(AppError::Findings(format!(
+                    "{} ids are shared by several paths",
+                    remaining
+                ))
+                .into());
+            }
+        }
+        Command::Dedupe {
+            root_dir,
+            apply,
+            json,
+        } => {
+            let root = provide_root(root_dir)?;
+            let collisions = commands::collisions::find_collisions(&root);
+            let resolved = match apply {
+                Some(keep) => commands::collisions::resolve_collisions(
                     &root,
-                    url,
-                    title,
-                    desc.to_owned(),
-                )
-                .await
-                {
-                    Ok(_) => {
-                        println!("Link saved successfully!");
+                    &collisions,
+                    (*keep).into(),
+                )?,
+                None => Default::default(),
+            };
+
+            let size = |paths: &[PathBuf]| {
+                paths
+                    .first()
+                    .and_then(|path| path.metadata().ok())
+                    .map_or(0, |metadata| metadata.len())
+            };
+            if *json {
+                let output = collisions
+                    .iter()
+                    .map(|(id, paths)| {
+                        let mut output = serde_json::json!({
+                            "id": id.to_string(),
+                            "size": size(paths),
+                            "paths": paths
+                                .iter()
+                                .map(|path| path.display().to_string())
+                                .collect::<Vec<_>>(),
+                        });
+                        if let Some(kept) = resolved.kept.get(id) {
+                            output["kept"] = kept.display().to_string().into();
+                            output["moved"] = resolved
+                                .moved
+                                .iter()
+                                .filter(|(from, _)| paths.contains(from))
+                                .map(|(from, to)| {
+                                    serde_json::json!({
+                                        "from": from.display().to_string(),
+                                        "to": to.display().to_string(),
+                                    })
+                                })
+                                .collect::<Vec<_>>()
+                                .into();
+                        }
+                        output
+                    })
+                    .collect::<Vec<_>>();
+                println!("{}", serde_json::Value::Array(output));
+            } else {
+                for (id, paths) in &collisions {
+                    println!("{} ({})", id, human_size(size(paths)));
+                    for path in paths {
+                        println!("\t{}", path.display());
                     }
-                    Err(e) => println!("{}", e),
+                }
+                for (from, to) in &resolved.moved {
+                    println!("Moved {} to {}", from.display(), to.display());
                 }
             }
+        }
+        Command::ReindexMigrate {
+            root_dir,
+            before_snapshot,
+            dry_run,
+        } => {
+            let plan =
+                commands::migrate::migration_plan(root_dir, before_snapshot)?;
 
-            Link::Load {
-                root_dir,
-                file_path,
-                id,
-            } => {
-                let root = provide_root(root_dir)?;
-                let link = commands::link::load_link(&root, file_path, id)?;
-                println!("Link data:\n{:?}", link);
+            for (path, old_id, new_id) in &plan.changed {
+                println!("{} -> {} {}", old_id, new_id, path.display());
             }
-        },
-        Command::File(file) => match &file {
+            if !plan.unmatched.is_empty() {
+                println!("These paths were moved or deleted:");
+                for path in &plan.unmatched {
+                    println!("\t{}", path.display());
+                }
+            }
+            if !plan.suspicious.is_empty() {
+                println!("These paths were left for manual review:");
+                for (path, reason) in &plan.suspicious {
+                    println!("\t{}: {}", path.display(), reason);
+                }
+            }
+
+            if !*dry_run {
+                let ids = plan
+                    .changed
+                    .iter()
+                    .map(|(_, old_id, new_id)| (*old_id, *new_id))
+                    .collect::<Vec<_>>();
+                let migrated =
+                    commands::migrate::migrate_metadata(root_dir, &ids)?;
+                println!("Migrated {} entries", migrated);
+            }
+        }
+        Command::Verify {
+            root_dir,
+            json,
+            fix,
+        } => {
+            let root = provide_root(root_dir)?;
+            let report = commands::index::verify_index(&root)?;
+
+            let paths = |paths: &Vec<PathBuf>| {
+                paths
+                    .iter()
+                    .map(|path| path.display().to_string())
+                    .collect::<Vec<_>>()
+            };
+            if *json {
+                let output = serde_json::json!({
+                    "missing": paths(&report.missing),
+                    "unindexed": paths(&report.unindexed),
+                    "modified": report
+                        .modified
+                        .iter()
+                        .map(|(path, indexed, current)| {
+                            serde_json::json!({
+                                "path": path.display().to_string(),
+                                "indexed": indexed.to_string(),
+                                "current": current.to_string(),
+                            })
+                        })
+                        .collect::<Vec<_>>(),
+                });
+                println!("{}", output);
+            } else {
+                let modified = report.modified.iter().map(|(path, _, _)| {
+                    format!("modified  {}", path.display())
+                });
+                let missing = report
+                    .missing
+                    .iter()
+                    .map(|path| format!("missing   {}", path.display()));
+                let unindexed = report
+                    .unindexed
+                    .iter()
+                    .map(|path| format!("unindexed {}", path.display()));
+                write_lines(
+                    &mut stdout().lock(),
+                    modified.chain(missing).chain(unindexed),
+                )?;
+
+                println!("Modified:  {}", report.modified.len());
+                println!("Missing:   {}", report.missing.len());
+                println!("Unindexed: {}", report.unindexed.len());
+            }
+
+            if !report.is_consistent() {
+                if !*fix {
+                    return Err(AppError::Findings(
+                        "The index is not consistent with the root".to_owned(),
+                    )
+                    .into());
+                }
+
+                let (count, _) = commands::index::rebuild_index(&root)?;
+                eprintln!("Rebuilt the index with {} resources", count);
+            }
+        }
+        Command::VerifyManifest {
+            root_dir,
+            format,
+            input,
+            json,
+        } => {
+            let root = provide_root(root_dir)?;
+            let entries = commands::manifest::parse_manifest(input, *format)?;
+            let report = commands::manifest::verify_manifest(&root, entries)?;
+
+            if *json {
+                let paths = |paths: &Vec<PathBuf>| {
+                    paths
+                        .iter()
+                        .map(|path| path.display().to_string())
+                        .collect::<Vec<_>>()
+                };
+                let output = serde_json::json!({
+                    "matched": paths(&report.matched),
+                    "mismatched": report
+                        .mismatched
+                        .iter()
+                        .map(|(path, expected, actual)| {
+                            serde_json::json!({
+                                "path": path.display().to_string(),
+                                "expected": expected,
+                                "actual": actual,
+                            })
+                        })
+                        .collect::<Vec<_>>(),
+                    "missing": paths(&report.missing),
+                    "unlisted": paths(&report.unlisted),
+                });
+                println!("{}", output);
+            } else {
+                for (path, _, _) in &report.mismatched {
+                    println!("mismatch {}", path.display());
+                }
+                for path in &report.missing {
+                    println!("missing  {}", path.display());
+                }
+                for path in &report.unlisted {
+                    println!("unlisted {}", path.display());
+                }
+
+                println!("Matched:    {}", report.matched.len());
+                println!("Mismatched: {}", report.mismatched.len());
+                println!("Missing:    {}", report.missing.len());
+                println!("Unlisted:   {}", report.unlisted.len());
+            }
+
+            if !report.mismatched.is_empty() {
+                return Err(AppError::Mismatch(format!(
+                    "{} files differ from the manifest",
+                    report.mismatched.len()
+                ))
+                .into());
+            }
+            if !report.missing.is_empty() || !report.unlisted.is_empty() {
+                return Err(AppError::Findings(format!(
+                    "{} listed files are missing, {} indexed files are unlisted",
+                    report.missing.len(),
+                    report.unlisted.len()
+                ))
+                .into());
+            }
+        }
+        Command::Monitor {
+            root_dir,
+            interval,
+            format,
+            record_history,
+            migrate_metadata,
+            migrate_window,
+            previews,
+        } => {
+            let millis = interval
+                .or(provide_config().monitor_interval)
+                .unwrap_or(1000);
+            let json =
+                matches!(output_format(format), Some(OutputFormat::Json));
+            monitor_index(
+                root_dir,
+                Some(millis),
+                json,
+                *record_history,
+                *migrate_metadata,
+                migrate_window.map(std::time::Duration::from_secs),
+                *previews,
+            )?
+        }
+        Command::Render {
+            path,
+            quality,
+            output,
+            output_format,
+            cache,
+            root_dir,
+            max_dimension,
+            force,
+        } => {
+            let path = path.as_ref().ok_or_else(|| {
+                AppError::RenderError("Path was not provided".to_owned())
+            })?;
+            // Only PDFs have a quality, images are downscaled as they are
+            let quality = quality.as_deref().unwrap_or("high");
+            commands::render::pdf_quality(quality)?;
+            if path.is_file() && !commands::render::is_renderable(path) {
+                return Err(AppError::RenderError(format!(
+                    "Unsupported file type: {}",
+                    path.display()
+                ))
+                .into());
+            }
+
+            let cache_root = match root_dir {
+                Some(_) => Some(provide_root(root_dir)?),
+                None if *cache => {
+                    let path = path.canonicalize()?;
+                    let root = path
+                        .ancestors()
+                        .find(|dir| storages_exists(dir))
+                        .ok_or_else(|| {
+                            AppError::InvalidOptions(format!(
+                                "No root contains {}, pass --root-dir",
+                                path.display()
+                            ))
+                        })?;
+                    Some(root.to_path_buf())
+                }
+                None => None,
+            };
+            if path.is_dir() {
+                commands::render::render_dir(
+                    path,
+                    quality,
+                    output,
+                    *output_format,
+                    *max_dimension,
+                    cache_root.as_deref(),
+                    *force,
+                )?;
+            } else if let Some(root) = &cache_root {
+                let (preview, cached) = commands::render::render_cached(
+                    path,
+                    quality,
+                    root,
+                    *output_format,
+                    *max_dimension,
+                )?;
+                if cached {
+                    println!("Cached {}", preview.display());
+                } else {
+                    println!("Rendered {}", preview.display());
+                }
+            } else {
+                commands::render::render_file(
+                    path,
+                    quality,
+                    output,
+                    *output_format,
+                    *max_dimension,
+                    *force,
+                )?;
+            }
+        }
+        Command::Index(index) => match &index {
+            IndexCommand::Rebuild { root_dir, json, .. } => {
+                let root = provide_root(root_dir)?;
+
+                if !is_quiet() && !json {
+                    println!("Rebuilding index of folder {}", root.display());
+                }
+                let (size, duration) = commands::index::rebuild_index(&root)?;
+                if *json {
+                    println!(
+                        "{}",
+                        serde_json::json!({
+                            "resources": size,
+                            "millis": duration.as_millis() as u64,
+                        })
+                    );
+                } else if !is_quiet() {
+                    println!("Indexed {} resources in {:?}", size, duration);
+                }
+            }
+            IndexCommand::Update { root_dir, json } => {
+                let root = provide_root(root_dir)?;
+                let (events, duration) = commands::index::update_index(&root)?;
+
+                let count = |kind| {
+                    events
+                        .iter()
+                        .filter(|event| event.kind == kind)
+                        .count()
+                };
+                let (added, updated, removed) =
+                    (count("added"), count("updated"), count("removed"));
+                if *json {
+                    println!(
+                        "{}",
+                        serde_json::json!({
+                            "added": added,
+                            "updated": updated,
+                            "removed": removed,
+                            "millis": duration.as_millis() as u64,
+                        })
+                    );
+                } else if !is_quiet() {
+                    println!(
+                        "Added {}, updated {}, removed {} in {:?}",
+                        added, updated, removed, duration
+                    );
+                }
+            }
+            IndexCommand::Snapshot { root_dir, output } => {
+                let root = provide_root(root_dir)?;
+                let snapshot = commands::migrate::take_snapshot(&root)?;
+
+                match output {
+                    Some(path) => std::fs::write(path, snapshot.to_string())?,
+                    None => println!("{}", snapshot),
+                }
+            }
+            IndexCommand::Stats {
+                root_dir,
+                top,
+                json,
+            } => {
+                let root = provide_root(root_dir)?;
+                let stats = commands::index::index_stats(&root, *top);
+
+                let to_rfc3339 = |time: Option<std::time::SystemTime>| {
+                    time.map(|time| DateTime::<Utc>::from(time).to_rfc3339())
+                };
+
+                if *json {
+                    let kinds = stats
+                        .kinds
+                        .iter()
+                        .map(|(kind, (paths, bytes))| {
+                            (
+                                kind.clone(),
+                                serde_json::json!({
+                                    "paths": paths,
+                                    "bytes": bytes,
+                                }),
+                            )
+                        })
+                        .collect::<serde_json::Map<_, _>>();
+                    let largest = stats
+                        .largest
+                        .iter()
+                        .map(|(path, bytes)| {
+                            serde_json::json!({
+                                "path": path,
+                                "bytes": bytes,
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    println!(
+                        "{}",
+                        serde_json::json!({
+                            "resources": stats.resources,
+                            "paths": stats.paths,
+                            "collisions": stats.collisions,
+                            "bytes": stats.bytes,
+                            "kinds": kinds,
+                            "largest": largest,
+                            "oldest": to_rfc3339(stats.oldest),
+                            "newest": to_rfc3339(stats.newest),
+                        })
+                    );
+                    return Ok(());
+                }
+
+                let format_time = |time: Option<std::time::SystemTime>| {
+                    to_rfc3339(time).unwrap_or_else(|| "-".to_owned())
+                };
+
+                println!("Resources:  {}", stats.resources);
+                println!("Paths:      {}", stats.paths);
+                println!("Collisions: {}", stats.collisions);
+                println!("Size:       {}", human_size(stats.bytes));
+                println!("Oldest:     {}", format_time(stats.oldest));
+                println!("Newest:     {}", format_time(stats.newest));
+
+                println!();
+                for (kind, (paths, bytes)) in stats.kinds.iter() {
+                    println!(
+                        "{:<10}  {:>8} paths  {:>10}",
+                        kind,
+                        paths,
+                        human_size(*bytes)
+                    );
+                }
+
+                if !stats.largest.is_empty() {
+                    println!();
+                    println!("Largest files:");
+                    for (path, bytes) in stats.largest.iter() {
+                        println!(
+                            "{:>10}  {}",
+                            human_size(*bytes),
+                            path.display()
+                        );
+                    }
+                }
+            }
+            IndexCommand::History {
+                command: Some(HistoryCommand::Replay { root_dir, until }),
+                ..
+            } => {
+                let root = provide_root(root_dir)?;
+                let until = parse_time(until)?.timestamp().max(0) as u64;
+
+                let (membership, gap) =
+                    commands::history::replay_history(&root, until)?;
+                if gap {
+                    eprintln!(
+                        "Warning: history before the requested time was truncated, the result may be incomplete"
+                    );
+                }
+                for (path, id) in membership {
+                    println!("{} {}", id, path.display());
+                }
+            }
+            IndexCommand::History {
+                command: None,
+                root_dir,
+                path,
+                id,
+                since,
+            } => {
+                let root = provide_root(root_dir)?;
+                let since = since
+                    .as_ref()
+                    .map(|since| parse_time(since))
+                    .transpose()?
+                    .map(|since| since.timestamp().max(0) as u64);
+
+                let records =
+                    commands::history::query_history(&root, path, id, since)?;
+                for record in records {
+                    let time = Utc
+                        .timestamp_opt(record.time as i64, 0)
+                        .single()
+                        .map(|time| time.to_rfc3339())
+                        .unwrap_or_else(|| record.time.to_string());
+                    println!(
+                        "{} {} {} {}",
+                        time,
+                        record.kind,
+                        record.id,
+                        record.path.display()
+                    );
+                }
+            }
+        },
+        Command::Link(link) => match &link {
+            Link::Create {
+                root_dir,
+                url,
+                title,
+                desc,
+                offline,
+            } => {
+                let root = provide_root(root_dir)?;
+                let url = url.as_ref().ok_or_else(|| {
+                    AppError::LinkCreationError(
+                        "Url was not provided".to_owned(),
+                    )
+                })?;
+
+                let offline = *offline
+                    || provide_config().fetch_link_metadata == Some(false);
+
+                println!("Saving link...");
+
+                commands::link::create_link(
+                    &root,
+                    url,
+                    title.to_owned(),
+                    desc.to_owned(),
+                    offline,
+                )
+                .await?;
+                println!("Link saved successfully!");
+            }
+
+            Link::Load {
+                root_dir,
+                file_path,
+                id,
+                format,
+            } => {
+                let root = provide_root(root_dir)?;
+                let link = commands::link::load_link(&root, file_path, id)?;
+
+                if let Some(OutputFormat::Json) = output_format(format) {
+                    let output = serde_json::json!({
+                        "url": link.url.as_str(),
+                        "title": link.prop.title,
+                        "desc": link.prop.desc,
+                    });
+                    println!("{}", output);
+                } else {
+                    println!("url:   {}", link.url);
+                    println!("title: {}", link.prop.title);
+                    println!(
+                        "desc:  {}",
+                        link.prop.desc.as_deref().unwrap_or("")
+                    );
+                }
+            }
+
+            Link::Open {
+                root_dir,
+                ids,
+                print_only,
+            } => {
+                let root = provide_root(&Some(root_dir.clone()))?;
+                for id in ids {
+                    commands::link::open_link(&root, *id, *print_only)?;
+                }
+            }
+
+            Link::Delete { root_dir, id } => {
+                let root = provide_root(root_dir)?;
+                commands::link::delete_link(&root, *id)?;
+                println!("Link {} deleted", id);
+            }
+
+            Link::Update {
+                root_dir,
+                id,
+                url,
+                title,
+                desc,
+            } => {
+                let root = provide_root(root_dir)?;
+                let new_id = commands::link::update_link(
+                    &root,
+                    *id,
+                    url.to_owned(),
+                    title.to_owned(),
+                    desc.to_owned(),
+                )
+                .await?;
+
+                if new_id != *id {
+                    println!("Link updated, its new id is {}", new_id);
+                } else {
+                    println!("Link updated");
+                }
+            }
+
+            Link::List {
+                root_dir,
+                format,
+                json,
+                filter_tag,
+            } => {
+                let root = provide_root(root_dir)?;
+                let links = commands::link::list_links(&root, filter_tag);
+
+                let format = if *json {
+                    Some(OutputFormat::Json)
+                } else {
+                    output_format(format)
+                };
+                if let Some(OutputFormat::Json) = format {
+                    let output = links
+                        .iter()
+                        .map(|(id, link)| {
+                            serde_json::json!({
+                                "id": id.to_string(),
+                                "url": link.url.as_str(),
+                                "title": link.prop.title,
+                                "desc": link.prop.desc,
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    println!("{}", serde_json::Value::Array(output));
+                } else {
+                    let longest_id = links
+                        .iter()
+                        .map(|(id, _)| id.to_string().len())
+                        .max()
+                        .unwrap_or(0);
+                    let longest_url = links
+                        .iter()
+                        .map(|(_, link)| link.url.as_str().len())
+                        .max()
+                        .unwrap_or(0);
+
+                    for (id, link) in &links {
+                        println!(
+                            "{:id_width$} {:url_width$} {}",
+                            id.to_string(),
+                            link.url.as_str(),
+                            link.prop.title,
+                            id_width = longest_id,
+                            url_width = longest_url
+                        );
+                    }
+                }
+            }
+        },
+        Command::File(file) => match &file {
             FileCommand::Append {
                 root_dir,
                 storage,
                 id,
                 content,
+                stdin: _,
                 format,
                 type_,
+                dry_run,
             } => {
                 let (file_path, storage_type) =
-                    translate_storage(&Some(root_dir.to_owned()), storage)
-                        .ok_or(AppError::StorageNotFound(storage.to_owned()))?;
-
-                let storage_type = storage_type.unwrap_or(match type_ {
-                    Some(t) => *t,
-                    None => StorageType::File,
-                });
+                    resolve_storage(root_dir, storage, type_)?;
 
                 let format = format.unwrap_or(Format::Raw);
 
-                let mut storage = Storage::new(file_path, storage_type)?;
-
-                let resource_id = ResourceId::from_str(id)?;
-
-                storage.append(resource_id, content, format)?;
+                let resource_id = commands::id::parse_id(id)?;
+
+                let content = read_content(content, storage_type)?;
+                if *dry_run {
+                    commands::file::dry_run_write(
+                        "append",
+                        resource_id,
+                        &content,
+                        format,
+                        &file_path,
+                        storage_type,
+                    );
+                } else {
+                    let mut storage = Storage::new(file_path, storage_type)?;
+                    storage.append(resource_id, &content, format)?;
+                }
             }
 
             FileCommand::Insert {
@@ -538,25 +1923,89 @@ async fn main() -> anyhow::Result<()> {
                 storage,
                 id,
                 content,
+                stdin: _,
                 format,
                 type_,
+                dry_run,
             } => {
                 let (file_path, storage_type) =
-                    translate_storage(&Some(root_dir.to_owned()), storage)
-                        .ok_or(AppError::StorageNotFound(storage.to_owned()))?;
-
-                let storage_type = storage_type.unwrap_or(match type_ {
-                    Some(t) => *t,
-                    None => StorageType::File,
-                });
+                    resolve_storage(root_dir, storage, type_)?;
 
                 let format = format.unwrap_or(Format::Raw);
 
-                let mut storage = Storage::new(file_path, storage_type)?;
+                let resource_id = commands::id::parse_id(id)?;
+
+                let content = read_content(content, storage_type)?;
+                if *dry_run {
+                    commands::file::dry_run_write(
+                        "insert",
+                        resource_id,
+                        &content,
+                        format,
+                        &file_path,
+                        storage_type,
+                    );
+                } else {
+                    let mut storage = Storage::new(file_path, storage_type)?;
+                    storage.insert(resource_id, &content, format)?;
+                }
+            }
+
+            FileCommand::ListIds {
+                root_dir,
+                storage,
+                type_,
+                format,
+            } => {
+                let mut storage = commands::storage::open_storage(
+                    &provide_root(root_dir)?,
+                    storage,
+                    type_,
+                )?;
+
+                let mut ids = storage
+                    .entries()?
+                    .into_iter()
+                    .map(|(id, _)| id.to_string())
+                    .collect::<Vec<_>>();
+                ids.sort();
+
+                if let Some(OutputFormat::Json) = output_format(format) {
+                    println!("{}", serde_json::json!(ids));
+                } else {
+                    for id in ids {
+                        println!("{}", id);
+                    }
+                }
+            }
 
-                let resource_id = ResourceId::from_str(id)?;
+            FileCommand::BulkInsert {
+                root_dir,
+                storage,
+                type_,
+                dry_run,
+            } => {
+                let mut storage = commands::storage::open_storage(
+                    &provide_root(root_dir)?,
+                    storage,
+                    type_,
+                )?;
+
+                let (inserted, failed) =
+                    commands::storage::bulk_insert(&mut storage, *dry_run)?;
+                if *dry_run {
+                    println!("Would insert {} entries", inserted);
+                } else {
+                    println!("Inserted {} entries", inserted);
+                }
 
-                storage.insert(resource_id, content, format)?;
+                if failed > 0 {
+                    return Err(AppError::ImportError(format!(
+                        "{} entries could not be parsed",
+                        failed
+                    ))
+                    .into());
+                }
             }
 
             FileCommand::Read {
@@ -564,25 +2013,63 @@ async fn main() -> anyhow::Result<()> {
                 storage,
                 id,
                 type_,
+                output,
+                key,
             } => {
                 let (file_path, storage_type) =
-                    translate_storage(&Some(root_dir.to_owned()), storage)
-                        .ok_or(AppError::StorageNotFound(storage.to_owned()))?;
-
-                let storage_type = storage_type.unwrap_or(match type_ {
-                    Some(t) => *t,
-                    None => StorageType::File,
-                });
+                    resolve_storage(root_dir, storage, type_)?;
 
                 let mut storage = Storage::new(file_path, storage_type)?;
 
-                let resource_id = ResourceId::from_str(id)?;
+                let resource_id = commands::id::parse_id(id)?;
 
-                let output = storage.read(resource_id)?;
+                if let Some(key) = key {
+                    let value = storage.read(resource_id)?;
+                    println!("{}", commands::file::extract_key(&value, key)?);
+                    return Ok(());
+                }
 
-                println!("{}", output);
+                match output {
+                    Some(path) => {
+                        let mut file = BufWriter::new(File::create(path)?);
+                        storage.read_to(resource_id, &mut file)?;
+                        file.flush()?;
+                    }
+                    None => {
+                        let mut stdout = stdout().lock();
+                        storage.read_to(resource_id, &mut stdout)?;
+                        writeln!(stdout)?;
+                    }
+                }
             }
         },
+        Command::Roots(roots) => {
+            let config = roots_config()?;
+            match &roots {
+                RootsCommand::List => {
+                    let roots = commands::roots::read_roots(&config)?;
+                    if roots.is_empty() {
+                        println!("No roots registered in {}", config.display());
+                    }
+                    for root in roots {
+                        let status = if storages_exists(&root) {
+                            ""
+                        } else {
+                            " (no storages)"
+                        };
+                        println!("{}{}", root.display(), status);
+                    }
+                }
+                RootsCommand::Add { path, init } => {
+                    let root = commands::roots::add_root(&config, path, *init)?;
+                    println!("Root added:\n\t{}", root.display());
+                }
+                RootsCommand::Remove { path } => {
+                    let root = commands::roots::remove_root(&config, path)?;
+                    println!("Root removed:\n\t{}", root.display());
+                }
+            }
+        }
         Command::Storage(cmd) => match &cmd {
             StorageCommand::List {
                 root_dir,
@@ -599,14 +2086,9 @@ async fn main() -> anyhow::Result<()> {
 
                 let versions = versions.unwrap_or(false);
 
+                let root_dir = provide_root(root_dir)?;
                 let (file_path, storage_type) =
-                    translate_storage(root_dir, storage)
-                        .ok_or(AppError::StorageNotFound(storage.to_owned()))?;
-
-                let storage_type = storage_type.unwrap_or(match type_ {
-                    Some(t) => *t,
-                    None => StorageType::File,
-                });
+                    resolve_storage(&root_dir, storage, type_)?;
 
                 let mut storage = Storage::new(file_path, storage_type)?;
 
@@ -616,7 +2098,554 @@ async fn main() -> anyhow::Result<()> {
 
                 println!("{}", output);
             }
+
+            StorageCommand::Du {
+                root_dir,
+                storage,
+                top,
+                format,
+            } => {
+                let root = provide_root(root_dir)?;
+                let names = match storage {
+                    Some(storage) => vec![storage.as_str()],
+                    None => vec!["tags", "scores", "properties", "metadata"],
+                };
+
+                let index = util::provide_index(&root);
+                let mut reports = Vec::new();
+                for name in names {
+                    // Skip the well-known storages which were never written
+                    if storage.is_none() {
+                        match translate_storage(&Some(root.clone()), name) {
+                            Some((path, _)) if path.exists() => {}
+                            _ => continue,
+                        }
+                    }
+
+                    let mut opened =
+                        commands::storage::open_storage(&root, name, &None)?;
+                    let usage = commands::storage::storage_usage(
+                        &mut opened,
+                        top.unwrap_or(10),
+                    )?;
+                    reports.push((name, usage));
+                }
+
+                let path_of = |id: &ResourceId| {
+                    index
+                        .id2path
+                        .get(id)
+                        .map(|path| path.as_path().display().to_string())
+                };
+
+                if let Some(OutputFormat::Json) = output_format(format) {
+                    let output = reports
+                        .iter()
+                        .map(|(name, usage)| {
+                            serde_json::json!({
+                                "storage": name,
+                                "total": usage.total,
+                                "count": usage.count,
+                                "mean": usage.mean,
+                                "median": usage.median,
+                                "max": usage.max,
+                                "largest": usage
+                                    .largest
+                                    .iter()
+                                    .map(|(id, size)| serde_json::json!({
+                                        "id": id.to_string(),
+                                        "size": size,
+                                        "path": path_of(id),
+                                    }))
+                                    .collect::<Vec<_>>(),
+                            })
+                        })
+                        .collect::<Vec<_>>();
+                    println!("{}", serde_json::Value::Array(output));
+                } else {
+                    for (name, usage) in &reports {
+                        println!(
+                            "{}: {} bytes in {} entries, mean {}, median {}, max {}",
+                            name,
+                            usage.total,
+                            usage.count,
+                            usage.mean,
+                            usage.median,
+                            usage.max
+                        );
+                        for (id, size) in &usage.largest {
+                            println!(
+                                "\t{:>10} {} {}",
+                                size,
+                                id,
+                                path_of(id).unwrap_or_default()
+                            );
+                        }
+                    }
+                }
+            }
+
+            StorageCommand::Truncate {
+                root_dir,
+                storage: storage_name,
+                id,
+                max_bytes,
+                yes,
+                type_,
+                dry_run,
+            } => {
+                let mut storage = commands::storage::open_storage(
+                    root_dir,
+                    storage_name,
+                    type_,
+                )?;
+                let journal = root_dir
+                    .join(arklib::ARK_FOLDER)
+                    .join(commands::storage::TRUNCATE_JOURNAL);
+                commands::storage::truncate_value(
+                    &mut storage,
+                    storage_name,
+                    *id,
+                    *max_bytes,
+                    &journal,
+                    *yes,
+                    *dry_run,
+                )?;
+            }
+
+            StorageCommand::Dump {
+                root_dir,
+                storage,
+                type_,
+            } => {
+                let mut storage = commands::storage::open_storage(
+                    &provide_root(root_dir)?,
+                    storage,
+                    type_,
+                )?;
+
+                let dump = commands::storage::dump_storage(&mut storage)?;
+
+                println!("{}", dump);
+            }
+
+            StorageCommand::Import {
+                root_dir,
+                storage,
+                input,
+                merge: _,
+                replace,
+                type_,
+                dry_run,
+            } => {
+                let mut storage = commands::storage::open_storage(
+                    &provide_root(root_dir)?,
+                    storage,
+                    type_,
+                )?;
+
+                let written = commands::storage::import_storage(
+                    &mut storage,
+                    input,
+                    *replace,
+                    *dry_run,
+                )?;
+
+                if *dry_run {
+                    println!("Would import {} entries", written);
+                } else {
+                    println!("Imported {} entries", written);
+                }
+            }
+
+            StorageCommand::Sync {
+                root_a,
+                root_b,
+                storage,
+                prefer,
+                dry_run,
+                type_,
+            } => {
+                let mut storage_a =
+                    commands::storage::open_storage(root_a, storage, type_)?;
+                let mut storage_b =
+                    commands::storage::open_storage(root_b, storage, type_)?;
+
+                commands::storage::sync_storages(
+                    &mut storage_a,
+                    &mut storage_b,
+                    prefer.unwrap_or(SyncPreference::Fail),
+                    *dry_run,
+                )?;
+            }
+            StorageCommand::Merge {
+                src_root,
+                dst_root,
+                storage,
+                strategy,
+                type_,
+                dry_run,
+            } => {
+                let mut src =
+                    commands::storage::open_storage(src_root, storage, type_)?;
+                let mut dst =
+                    commands::storage::open_storage(dst_root, storage, type_)?;
+
+                let written = commands::storage::merge_storages(
+                    &mut src,
+                    &mut dst,
+                    strategy.unwrap_or(MergeStrategy::KeepDst),
+                    *dry_run,
+                )?;
+                if *dry_run {
+                    println!("Would merge {} entries", written);
+                } else {
+                    println!("Merged {} entries", written);
+                }
+            }
+            StorageCommand::Diff {
+                root_a,
+                root_b,
+                storage,
+                type_,
+                format,
+            } => {
+                let mut storage_a =
+                    commands::storage::open_storage(root_a, storage, type_)?;
+                let mut storage_b =
+                    commands::storage::open_storage(root_b, storage, type_)?;
+
+                let diff = commands::storage::diff_storages(
+                    &mut storage_a,
+                    &mut storage_b,
+                )?;
+
+                if let Some(OutputFormat::Json) = output_format(format) {
+                    let values = |entries: &[(ResourceId, String)]| {
+                        entries
+                            .iter()
+                            .map(|(id, value)| {
+                                (
+                                    id.to_string(),
+                                    serde_json::Value::String(value.clone()),
+                                )
+                            })
+                            .collect::<serde_json::Map<_, _>>()
+                    };
+                    let differing = diff
+                        .differing
+                        .iter()
+                        .map(|(id, a, b)| {
+                            (
+                                id.to_string(),
+                                serde_json::json!({"a": a, "b": b}),
+                            )
+                        })
+                        .collect::<serde_json::Map<_, _>>();
+
+                    println!(
+                        "{}",
+                        serde_json::json!({
+                            "only_a": values(&diff.only_a),
+                            "only_b": values(&diff.only_b),
+                            "differing": differing,
+                        })
+                    );
+                } else {
+                    for (id, value) in &diff.only_a {
+                        println!("only a {}: {}", id, value);
+                    }
+                    for (id, value) in &diff.only_b {
+                        println!("only b {}: {}", id, value);
+                    }
+                    for (id, a, b) in &diff.differing {
+                        println!("differ {}: a = {}, b = {}", id, a, b);
+                    }
+                }
+            }
+        },
+        Command::Config(config) => {
+            let mut current = load_config()?;
+            match &config {
+                ConfigCommand::Get { key } => match current.get(key)? {
+                    Some(value) => println!("{}", value),
+                    None => println!("{} is not set", key),
+                },
+                ConfigCommand::Set { key, value } => {
+                    current.set(key, value)?;
+                    save_config(&current)?;
+                }
+            }
+        }
+        Command::Id {
+            command: Some(IdCommand::Expand { root_dir, prefix }),
+            ..
+        } => {
+            let ids = util::provide_index(&provide_root(root_dir)?)
+                .id2path
+                .keys()
+                .copied()
+                .collect::<Vec<_>>();
+            println!("{}", commands::id::expand_id(&ids, prefix)?);
+        }
+        Command::Gc {
+            root_dir,
+            storage,
+            yes,
+            dry_run,
+        } => {
+            let root = provide_root(root_dir)?;
+            let names = if storage.is_empty() {
+                commands::migrate::USER_STORAGES
+                    .iter()
+                    .map(|name| name.to_string())
+                    .collect()
+            } else {
+                storage.clone()
+            };
+
+            // Providing the index updates it, so files deleted
+            // since the last run are not counted as indexed
+            let indexed = util::provide_index(&root)
+                .id2path
+                .keys()
+                .copied()
+                .collect::<HashSet<_>>();
+
+            for name in &names {
+                // Skip the well-known storages which were never written
+                if storage.is_empty() {
+                    match translate_storage(&Some(root.clone()), name) {
+                        Some((path, _)) if path.exists() => {}
+                        _ => continue,
+                    }
+                }
+
+                let mut opened =
+                    commands::storage::open_storage(&root, name, &None)?;
+                let orphans =
+                    commands::storage::find_orphans(&mut opened, &indexed)?;
+                if orphans.is_empty() {
+                    continue;
+                }
+
+                println!("{}:", name);
+                for (id, value) in &orphans {
+                    println!("\t{}: {}", id, value);
+                }
+
+                let question =
+                    format!("Remove {} entries from {}?", orphans.len(), name);
+                if *dry_run || (!yes && !confirm(&question)?) {
+                    continue;
+                }
+
+                let ids = orphans
+                    .iter()
+                    .map(|(id, _)| *id)
+                    .collect::<Vec<_>>();
+                let removed =
+                    commands::storage::remove_entries(&mut opened, &ids)?;
+                println!("Removed {} entries from {}", removed, name);
+            }
+        }
+        Command::Import {
+            root_dir,
+            input,
+            merge,
+        } => {
+            let root = provide_root(root_dir)?;
+            let export: serde_json::Value = match input {
+                Some(path) => serde_json::from_reader(File::open(path)?),
+                None => serde_json::from_reader(std::io::stdin()),
+            }
+            .map_err(|e| AppError::ImportError(e.to_string()))?;
+
+            let report =
+                commands::export::import_metadata(&root, &export, *merge)?;
+            for id in &report.unindexed {
+                eprintln!(
+                    "Warning: {} is not indexed in {}",
+                    id,
+                    root.display()
+                );
+            }
+            for (name, written) in &report.written {
+                println!("Imported {} entries into {}", written, name);
+            }
+        }
+        Command::Export { root_dir, output } => {
+            let root = provide_root(root_dir)?;
+            let export = commands::export::export_metadata(&root)?;
+            let export = serde_json::to_string_pretty(&export)
+                .map_err(|e| AppError::FileOperationError(e.to_string()))?;
+
+            match output {
+                Some(path) => std::fs::write(path, export + "\n")?,
+                None => println!("{}", export),
+            }
+        }
+        Command::Find {
+            root_dir,
+            ids,
+            prefix,
+        } => {
+            let root = provide_root(&Some(root_dir.clone()))?;
+            let index = util::provide_index(&root);
+            let indexed = index.id2path.keys().copied().collect::<Vec<_>>();
+
+            let mut missing = 0;
+            for id in ids {
+                let id = if *prefix {
+                    commands::id::expand_id(&indexed, id)?
+                } else {
+                    commands::id::parse_id(id)?
+                };
+
+                let paths = commands::id::find_paths(&index, id);
+                if paths.is_empty() {
+                    eprintln!("Resource not found: {}", id);
+                    missing += 1;
+                }
+                for path in paths {
+                    println!("{}", path.display());
+                }
+            }
+
+            if missing > 0 {
+                return Err(AppError::Findings(format!(
+                    "{} ids were not found",
+                    missing
+                ))
+                .into());
+            }
+        }
+        Command::Search {
+            root_dir,
+            query,
+            regex,
+            case_sensitive,
+            storage,
+            content,
+            max_size,
+            json,
+        } => {
+            let query = commands::search::compile_query(
+                query,
+                *regex,
+                *case_sensitive,
+            )?;
+            let matches = commands::search::search(
+                &provide_root(root_dir)?,
+                &query,
+                storage,
+                content.then_some(*max_size),
+            )?;
+
+            for found in &matches {
+                if *json {
+                    println!("{}", found.to_json());
+                } else {
+                    println!(
+                        "{}\t{}\t{}",
+                        found.id, found.source, found.snippet
+                    );
+                }
+            }
+
+            if matches.is_empty() {
+                return Err(AppError::Findings("No matches".to_owned()).into());
+            }
+        }
+        Command::Id {
+            command: None,
+            paths,
+            recursive,
+        } => {
+            if paths.is_empty() {
+                return Err(AppError::InvalidOptions(
+                    "expected files to compute the ids of".to_owned(),
+                )
+                .into());
+            }
+
+            let ids = commands::id::compute_ids(paths, *recursive)?;
+            if paths.len() == 1 && !paths[0].is_dir() {
+                println!("{}", ids[0].0);
+            } else {
+                for (id, path) in ids {
+                    println!("{}\t{}", id, path.display());
+                }
+            }
+        }
+        Command::Metadata {
+            command: Some(MetadataCommand::Index { root_dir, force }),
+            ..
+        } => {
+            let root = provide_root(root_dir)?;
+            let report = commands::metadata::index_metadata(&root, *force)?;
+            println!(
+                "Extracted metadata of {} resources, {} were skipped, {} failed",
+                report.extracted, report.skipped, report.failed
+            );
+        }
+        Command::Metadata {
+            command: None,
+            root_dir,
+            id,
+            force,
+        } => {
+            let root = provide_root(root_dir)?;
+            let id = id.ok_or_else(|| {
+                AppError::InvalidOptions(
+                    "expected the id of a resource".to_owned(),
+                )
+            })?;
+
+            let (metadata, extracted) =
+                commands::metadata::store_metadata(&root, id, *force)?;
+            if !extracted && !is_quiet() {
+                let found = metadata
+                    .as_object()
+                    .map_or(true, |metadata| !metadata.is_empty());
+                if *force || !found {
+                    eprintln!("No metadata found in the resource");
+                } else {
+                    eprintln!(
+                        "Kept metadata extracted before, use --force to replace it"
+                    );
+                }
+            }
+            println!("{}", metadata);
+        }
+        Command::Properties(properties) => match &properties {
+            PropertiesCommand::Set {
+                root_dir,
+                id,
+                pairs,
+                delete,
+            } => {
+                let root = provide_root(&Some(root_dir.clone()))?;
+                let properties = commands::properties::set_properties(
+                    &root, *id, pairs, delete,
+                )?;
+                println!("{}", serde_json::Value::Object(properties));
+            }
+            PropertiesCommand::Get { root_dir, id, key } => {
+                let root = provide_root(&Some(root_dir.clone()))?;
+                let properties =
+                    commands::properties::read_properties(&root, *id)?;
+                let value = serde_json::Value::Object(properties);
+                match key {
+                    Some(key) => {
+                        println!("{}", commands::file::lookup_key(&value, key)?)
+                    }
+                    None => println!("{}", value),
+                }
+            }
         },
+        // Printed before loading the app id
+        Command::Completions { .. } | Command::Man { .. } => {}
     };
 
     Ok(())
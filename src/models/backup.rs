@@ -0,0 +1,41 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Compression::None => "tar",
+            Compression::Gzip => "tar.gz",
+            Compression::Zstd => "tar.zst",
+        }
+    }
+
+    /// Timestamp and compression of a `backup-<timestamp>.<extension>` name
+    pub fn parse_archive_name(name: &str) -> Option<(u64, Self)> {
+        let (timestamp, extension) =
+            name.strip_prefix("backup-")?.split_once('.')?;
+        let compression =
+            [Compression::None, Compression::Gzip, Compression::Zstd]
+                .into_iter()
+                .find(|compression| compression.extension() == extension)?;
+
+        Some((timestamp.parse().ok()?, compression))
+    }
+}
+
+impl std::str::FromStr for Compression {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(Compression::None),
+            "gzip" => Ok(Compression::Gzip),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err("Compression must be either 'none', 'gzip' or 'zstd'"),
+        }
+    }
+}
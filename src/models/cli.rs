@@ -1,10 +1,22 @@
 use std::path::PathBuf;
 
 use arklib::id::ResourceId;
-use clap::{Parser, Subcommand};
+use clap::{Parser, Subcommand, ValueHint};
+use clap_complete::Shell;
+
+use crate::commands::id::parse_id;
 
 use super::{
-    entry::EntryOutput, format::Format, sort::Sort, storage::StorageType,
+    backup::Compression,
+    collisions::{Keep, Resolution},
+    entry::EntryOutput,
+    format::Format,
+    kind::Kind,
+    manifest::ManifestFormat,
+    output::OutputFormat,
+    render::RenderFormat,
+    sort::{Sort, SortKey},
+    storage::{MergeStrategy, StorageType, SyncPreference},
 };
 
 #[derive(Parser, Debug)]
@@ -13,34 +25,133 @@ use super::{
 pub struct Cli {
     #[clap(subcommand)]
     pub command: Command,
+
+    /// Root used by commands when it is not given to them explicitly.
+    /// Takes precedence over ARK_ROOT, the current directory is used
+    /// when neither is set
+    #[clap(
+        long = "root-dir",
+        global = true,
+        env = "ARK_ROOT",
+        parse(from_os_str),
+        value_hint = ValueHint::DirPath
+    )]
+    pub default_root: Option<PathBuf>,
+
+    /// Hide progress bars and informational messages
+    #[clap(short, long, global = true, action)]
+    pub quiet: bool,
 }
 
 #[derive(Subcommand, Debug)]
 pub enum Command {
+    #[clap(args_conflicts_with_subcommands = true)]
     Backup {
+        #[clap(subcommand)]
+        command: Option<BackupCommand>,
+
         #[clap(parse(from_os_str))]
         roots_cfg: Option<PathBuf>,
+
+        #[clap(long, action)]
+        full: bool,
+
+        #[clap(long, action)]
+        archive: bool,
+
+        #[clap(long, requires = "archive")]
+        compression: Option<Compression>,
+
+        #[clap(long, parse(from_os_str))]
+        output: Option<PathBuf>,
     },
 
     Collisions {
-        #[clap(parse(from_os_str))]
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        #[clap(short, long)]
+        format: Option<OutputFormat>,
+
+        #[clap(long)]
+        resolve: Option<Resolution>,
+
+        #[clap(long, action)]
+        short_ids: bool,
+    },
+
+    /// Print copies of the same content, largest first, and move
+    /// the redundant ones into `.ark/trash/<timestamp>`
+    Dedupe {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
         root_dir: Option<PathBuf>,
+
+        /// `keep-oldest` or `keep-newest`, without it nothing is moved
+        #[clap(long)]
+        apply: Option<Keep>,
+
+        #[clap(long, action)]
+        json: bool,
     },
 
     Monitor {
-        #[clap(parse(from_os_str))]
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
         root_dir: Option<PathBuf>,
         interval: Option<u64>,
+
+        #[clap(short, long)]
+        format: Option<OutputFormat>,
+
+        #[clap(long, action)]
+        record_history: bool,
+
+        #[clap(long, action)]
+        migrate_metadata: bool,
+
+        /// Also migrate the metadata of a path removed and added back with
+        /// another id within this many seconds, as tools replacing files do
+        #[clap(long, requires = "migrate_metadata")]
+        migrate_window: Option<u64>,
+
+        /// Render previews of new and changed resources into the cache
+        /// of the root
+        #[clap(long, action)]
+        previews: bool,
     },
 
     Render {
         #[clap(parse(from_os_str))]
         path: Option<PathBuf>,
         quality: Option<String>,
+
+        #[clap(long, parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        #[clap(long)]
+        output_format: Option<RenderFormat>,
+
+        /// Keep previews in the cache of the root, rendering only
+        /// the files which are not cached yet
+        #[clap(long, action, conflicts_with = "output")]
+        cache: bool,
+
+        /// Root whose cache is used, by default the closest root
+        /// containing the path
+        #[clap(long, parse(from_os_str), requires = "cache")]
+        root_dir: Option<PathBuf>,
+
+        /// Downscale previews and thumbnails to fit into a square
+        /// of this many pixels
+        #[clap(long)]
+        max_dimension: Option<u32>,
+
+        /// Overwrite existing files with the rendered ones
+        #[clap(long, action)]
+        force: bool,
     },
 
     List {
-        #[clap(parse(from_os_str))]
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
         root_dir: Option<PathBuf>,
 
         #[clap(long)]
@@ -67,10 +178,131 @@ pub enum Command {
         #[clap(long)]
         sort: Option<Sort>,
 
+        #[clap(long)]
+        sort_by: Option<SortKey>,
+
         #[clap(long)]
         filter: Option<String>,
+
+        #[clap(long, action)]
+        all_roots: bool,
+
+        #[clap(short, long)]
+        format: Option<OutputFormat>,
+
+        #[clap(long)]
+        preview_len: Option<usize>,
+
+        #[clap(long)]
+        limit: Option<usize>,
+
+        #[clap(long)]
+        offset: Option<usize>,
+
+        #[clap(long)]
+        min_score: Option<u32>,
+
+        #[clap(long)]
+        max_score: Option<u32>,
+
+        #[clap(long)]
+        since: Option<String>,
+
+        #[clap(long)]
+        until: Option<String>,
+
+        #[clap(long, multiple_occurrences = true)]
+        path_glob: Vec<String>,
+
+        #[clap(long, multiple_occurrences = true)]
+        exclude_glob: Vec<String>,
+
+        #[clap(long)]
+        date_format: Option<String>,
+
+        #[clap(long, action, conflicts_with = "date_format")]
+        iso: bool,
+
+        #[clap(long, action)]
+        short_ids: bool,
+
+        #[clap(short = '0', long, action, conflicts_with = "format")]
+        print0: bool,
+
+        #[clap(long, action)]
+        size: bool,
+
+        #[clap(long, action, requires = "size")]
+        bytes: bool,
+
+        #[clap(long, action)]
+        kind: bool,
+
+        #[clap(long)]
+        filter_kind: Option<Kind>,
+
+        /// Print the listing again whenever the index changes
+        #[clap(long, action)]
+        watch: bool,
+
+        /// Milliseconds between index updates with `--watch`
+        #[clap(long, requires = "watch")]
+        interval: Option<u64>,
+
+        /// Show a key of the extracted metadata, like `taken_at`
+        #[clap(long)]
+        metadata_key: Option<String>,
+
+        /// Show absolute paths instead of paths relative to the root
+        #[clap(long, alias = "absolute-paths", action)]
+        absolute: bool,
+
+        /// Show paths relative to the root, the default
+        #[clap(long, action, conflicts_with = "absolute")]
+        relative: bool,
+    },
+
+    ReindexMigrate {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: PathBuf,
+
+        #[clap(long, parse(from_os_str))]
+        before_snapshot: PathBuf,
+
+        #[clap(long, action)]
+        dry_run: bool,
+    },
+
+    /// Compare the stored index with the files of the root
+    Verify {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        #[clap(long, action)]
+        json: bool,
+
+        /// Rebuild the index if it is not consistent
+        #[clap(long, action)]
+        fix: bool,
     },
 
+    VerifyManifest {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        #[clap(long)]
+        format: ManifestFormat,
+
+        #[clap(short, long, parse(from_os_str))]
+        input: PathBuf,
+
+        #[clap(long, action)]
+        json: bool,
+    },
+
+    #[clap(subcommand)]
+    Index(IndexCommand),
+
     #[clap(subcommand)]
     Link(Link),
 
@@ -79,12 +311,336 @@ pub enum Command {
 
     #[clap(subcommand)]
     Storage(StorageCommand),
+
+    #[clap(subcommand)]
+    Roots(RootsCommand),
+
+    Completions {
+        shell: Shell,
+    },
+
+    /// Render man pages, the top-level one to stdout without `--output`
+    #[clap(hide = true)]
+    Man {
+        #[clap(short, long, parse(from_os_str), value_hint = ValueHint::DirPath)]
+        output: Option<PathBuf>,
+    },
+
+    #[clap(subcommand)]
+    Config(ConfigCommand),
+
+    /// Write metadata exported with `export` into the storages of a root
+    Import {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        /// Read from stdin when omitted
+        #[clap(short, long, parse(from_os_str))]
+        input: Option<PathBuf>,
+
+        #[clap(long, action)]
+        merge: bool,
+    },
+
+    /// Remove storage entries of resources which are not indexed anymore
+    Gc {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        /// Storages to clean, tags, scores and properties by default
+        #[clap(long, multiple_occurrences = true, use_delimiter = true)]
+        storage: Vec<String>,
+
+        #[clap(short, long, action)]
+        yes: bool,
+
+        #[clap(long, action)]
+        dry_run: bool,
+    },
+
+    /// Write tags, scores and properties of every indexed resource as JSON
+    Export {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        #[clap(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+
+    /// Print the paths of resources with the ids
+    Find {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: PathBuf,
+
+        #[clap(required = true)]
+        ids: Vec<String>,
+
+        /// Match unique prefixes of ids
+        #[clap(long, action)]
+        prefix: bool,
+    },
+
+    /// Search the values of tags, properties and links
+    #[clap(allow_missing_positional = true)]
+    Search {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        query: String,
+
+        /// Match the query as a regular expression
+        #[clap(long, action)]
+        regex: bool,
+
+        #[clap(long, action)]
+        case_sensitive: bool,
+
+        /// Storages to search: tags, properties or links
+        #[clap(long, multiple_occurrences = true, use_delimiter = true)]
+        storage: Vec<String>,
+
+        /// Also search text resources
+        #[clap(long, action)]
+        content: bool,
+
+        /// Largest text resource searched with `--content`, in bytes
+        #[clap(long, default_value = "1048576", requires = "content")]
+        max_size: u64,
+
+        #[clap(long, action)]
+        json: bool,
+    },
+
+    /// Compute ids of files, or work with ids of a root
+    #[clap(args_conflicts_with_subcommands = true)]
+    Id {
+        #[clap(subcommand)]
+        command: Option<IdCommand>,
+
+        #[clap(parse(from_os_str), value_hint = ValueHint::AnyPath)]
+        paths: Vec<PathBuf>,
+
+        #[clap(short, long, action)]
+        recursive: bool,
+    },
+
+    #[clap(subcommand)]
+    Properties(PropertiesCommand),
+
+    /// Extract embedded metadata of a resource into the `metadata` storage
+    #[clap(args_conflicts_with_subcommands = true)]
+    Metadata {
+        #[clap(subcommand)]
+        command: Option<MetadataCommand>,
+
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        #[clap(parse(try_from_str = parse_id))]
+        id: Option<ResourceId>,
+
+        /// Replace metadata extracted before
+        #[clap(long, action)]
+        force: bool,
+    },
 }
 
 #[derive(Subcommand, Debug)]
-pub enum StorageCommand {
+pub enum MetadataCommand {
+    /// Extract metadata of every indexed resource
+    Index {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        #[clap(long, action)]
+        force: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum IdCommand {
+    #[clap(allow_missing_positional = true)]
+    Expand {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        prefix: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum PropertiesCommand {
+    Set {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: PathBuf,
+
+        #[clap(parse(try_from_str = parse_id))]
+        id: ResourceId,
+
+        /// `key=value` pairs, `key=` sets the key to null
+        pairs: Vec<String>,
+
+        #[clap(long, multiple_occurrences = true)]
+        delete: Vec<String>,
+    },
+
+    Get {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: PathBuf,
+
+        #[clap(parse(try_from_str = parse_id))]
+        id: ResourceId,
+
+        key: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    Get { key: String },
+
+    Set { key: String, value: String },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum BackupCommand {
     List {
+        #[clap(long, parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        #[clap(short, long)]
+        format: Option<OutputFormat>,
+    },
+
+    Prune {
+        #[clap(long)]
+        keep_last: usize,
+
+        #[clap(long)]
+        keep_days: Option<u64>,
+
+        #[clap(short, long, action)]
+        yes: bool,
+
+        #[clap(long, action)]
+        dry_run: bool,
+
+        #[clap(long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+
+    /// Copies the storages of a backup folder or archive back into its roots
+    Restore {
+        /// Timestamp of the backup, as shown by `backup list`
+        timestamp: u64,
+
+        #[clap(short, long, action)]
+        yes: bool,
+
+        #[clap(long, action)]
+        dry_run: bool,
+
+        #[clap(long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum IndexCommand {
+    Rebuild {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        #[clap(long, action)]
+        json: bool,
+
+        /// Accepted for compatibility, the index is always rebuilt
+        /// even when it looks current
+        #[clap(long, action)]
+        force: bool,
+    },
+
+    /// Apply the changes of the root to the stored index
+    Update {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        #[clap(long, action)]
+        json: bool,
+    },
+
+    Snapshot {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        #[clap(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+    },
+
+    Stats {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        /// Amount of the largest files to show
+        #[clap(long, default_value = "10")]
+        top: usize,
+
+        #[clap(long, action)]
+        json: bool,
+    },
+
+    #[clap(args_conflicts_with_subcommands = true)]
+    History {
+        #[clap(subcommand)]
+        command: Option<HistoryCommand>,
+
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        #[clap(long)]
+        path: Option<String>,
+
+        #[clap(long, parse(try_from_str = parse_id))]
+        id: Option<ResourceId>,
+
+        #[clap(long)]
+        since: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum HistoryCommand {
+    Replay {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        #[clap(long)]
+        until: String,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum RootsCommand {
+    List,
+
+    Add {
+        #[clap(parse(from_os_str))]
+        path: PathBuf,
+
+        #[clap(long, action)]
+        init: bool,
+    },
+
+    Remove {
         #[clap(parse(from_os_str))]
+        path: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum StorageCommand {
+    List {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
         root_dir: Option<PathBuf>,
 
         storage: Option<String>,
@@ -95,46 +651,214 @@ pub enum StorageCommand {
         #[clap(short, long)]
         type_: Option<StorageType>,
     },
+
+    #[clap(allow_missing_positional = true)]
+    Dump {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        storage: String,
+
+        #[clap(short, long)]
+        type_: Option<StorageType>,
+    },
+
+    #[clap(allow_missing_positional = true)]
+    Import {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        storage: String,
+
+        #[clap(short, long, parse(from_os_str))]
+        input: Option<PathBuf>,
+
+        #[clap(long, action, conflicts_with = "replace")]
+        merge: bool,
+
+        #[clap(long, action)]
+        replace: bool,
+
+        #[clap(short, long)]
+        type_: Option<StorageType>,
+
+        #[clap(long, action)]
+        dry_run: bool,
+    },
+
+    Sync {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_a: PathBuf,
+
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_b: PathBuf,
+
+        storage: String,
+
+        #[clap(long)]
+        prefer: Option<SyncPreference>,
+
+        #[clap(long, action)]
+        dry_run: bool,
+
+        #[clap(short, long)]
+        type_: Option<StorageType>,
+    },
+
+    Merge {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        src_root: PathBuf,
+
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        dst_root: PathBuf,
+
+        storage: String,
+
+        #[clap(long)]
+        strategy: Option<MergeStrategy>,
+
+        #[clap(short, long)]
+        type_: Option<StorageType>,
+
+        #[clap(long, action)]
+        dry_run: bool,
+    },
+
+    Diff {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_a: PathBuf,
+
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_b: PathBuf,
+
+        storage: String,
+
+        #[clap(short, long)]
+        type_: Option<StorageType>,
+
+        #[clap(short, long)]
+        format: Option<OutputFormat>,
+    },
+
+    Du {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        storage: Option<String>,
+
+        #[clap(long)]
+        top: Option<usize>,
+
+        #[clap(short, long)]
+        format: Option<OutputFormat>,
+    },
+
+    Truncate {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: PathBuf,
+
+        storage: String,
+
+        #[clap(parse(try_from_str = parse_id))]
+        id: ResourceId,
+
+        #[clap(long)]
+        max_bytes: usize,
+
+        #[clap(short, long, action)]
+        yes: bool,
+
+        #[clap(short, long)]
+        type_: Option<StorageType>,
+
+        #[clap(long, action)]
+        dry_run: bool,
+    },
 }
 
 #[derive(Subcommand, Debug)]
 pub enum FileCommand {
     Append {
-        #[clap(parse(from_os_str))]
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
         root_dir: PathBuf,
 
         storage: String,
 
         id: String,
 
-        content: String,
+        /// Read from stdin when `-` or omitted
+        content: Option<String>,
+
+        /// Read the content from stdin, same as omitting it
+        #[clap(long, action, conflicts_with = "content")]
+        stdin: bool,
 
         #[clap(short, long)]
         format: Option<Format>,
 
         #[clap(short, long)]
         type_: Option<StorageType>,
+
+        #[clap(long, action)]
+        dry_run: bool,
     },
 
     Insert {
-        #[clap(parse(from_os_str))]
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
         root_dir: PathBuf,
 
         storage: String,
 
         id: String,
 
-        content: String,
+        /// Read from stdin when `-` or omitted
+        content: Option<String>,
+
+        /// Read the content from stdin, same as omitting it
+        #[clap(long, action, conflicts_with = "content")]
+        stdin: bool,
 
         #[clap(short, long)]
         format: Option<Format>,
 
         #[clap(short, long)]
         type_: Option<StorageType>,
+
+        #[clap(long, action)]
+        dry_run: bool,
+    },
+
+    #[clap(allow_missing_positional = true)]
+    ListIds {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        storage: String,
+
+        #[clap(short, long)]
+        type_: Option<StorageType>,
+
+        #[clap(short, long)]
+        format: Option<OutputFormat>,
+    },
+
+    /// Insert `<id>\t<value>` lines or a JSON object read from stdin
+    #[clap(allow_missing_positional = true)]
+    BulkInsert {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        storage: String,
+
+        #[clap(short, long)]
+        type_: Option<StorageType>,
+
+        #[clap(long, action)]
+        dry_run: bool,
     },
 
     Read {
-        #[clap(parse(from_os_str))]
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
         root_dir: PathBuf,
 
         storage: String,
@@ -143,27 +867,94 @@ pub enum FileCommand {
 
         #[clap(short, long)]
         type_: Option<StorageType>,
+
+        #[clap(short, long, parse(from_os_str))]
+        output: Option<PathBuf>,
+
+        /// Print only this field of a JSON value, e.g. `meta.author`
+        #[clap(long, conflicts_with = "output")]
+        key: Option<String>,
     },
 }
 
 #[derive(Subcommand, Debug)]
 pub enum Link {
     Create {
-        #[clap(parse(from_os_str))]
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
         root_dir: Option<PathBuf>,
 
         url: Option<String>,
         title: Option<String>,
         desc: Option<String>,
+
+        #[clap(long, action)]
+        offline: bool,
     },
 
     Load {
-        #[clap(parse(from_os_str))]
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
         root_dir: Option<PathBuf>,
 
         #[clap(parse(from_os_str))]
         file_path: Option<PathBuf>,
 
+        #[clap(parse(try_from_str = parse_id))]
         id: Option<ResourceId>,
+
+        #[clap(short, long)]
+        format: Option<OutputFormat>,
+    },
+
+    List {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        #[clap(short, long)]
+        format: Option<OutputFormat>,
+
+        /// Same as `--format json`
+        #[clap(long, action, conflicts_with = "format")]
+        json: bool,
+
+        #[clap(long)]
+        filter_tag: Option<String>,
+    },
+
+    Open {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: PathBuf,
+
+        #[clap(required = true, parse(try_from_str = parse_id))]
+        ids: Vec<ResourceId>,
+
+        #[clap(long, action)]
+        print_only: bool,
+    },
+
+    #[clap(allow_missing_positional = true)]
+    Delete {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        #[clap(parse(try_from_str = parse_id))]
+        id: ResourceId,
+    },
+
+    #[clap(allow_missing_positional = true)]
+    Update {
+        #[clap(parse(from_os_str), value_hint = ValueHint::DirPath)]
+        root_dir: Option<PathBuf>,
+
+        #[clap(parse(try_from_str = parse_id))]
+        id: ResourceId,
+
+        #[clap(long)]
+        url: Option<String>,
+
+        #[clap(long)]
+        title: Option<String>,
+
+        #[clap(long)]
+        desc: Option<String>,
     },
 }
@@ -0,0 +1,50 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    KeepFirst,
+    KeepOldest,
+    KeepNewest,
+    Interactive,
+}
+
+impl std::str::FromStr for Resolution {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "keep-first" => Ok(Resolution::KeepFirst),
+            "keep-oldest" => Ok(Resolution::KeepOldest),
+            "keep-newest" => Ok(Resolution::KeepNewest),
+            "interactive" => Ok(Resolution::Interactive),
+            _ => Err("Resolution must be one of 'keep-first', 'keep-oldest', \
+                'keep-newest' or 'interactive'"),
+        }
+    }
+}
+
+/// Copies kept by `dedupe --apply`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Keep {
+    Oldest,
+    Newest,
+}
+
+impl From<Keep> for Resolution {
+    fn from(keep: Keep) -> Self {
+        match keep {
+            Keep::Oldest => Resolution::KeepOldest,
+            Keep::Newest => Resolution::KeepNewest,
+        }
+    }
+}
+
+impl std::str::FromStr for Keep {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "keep-oldest" => Ok(Keep::Oldest),
+            "keep-newest" => Ok(Keep::Newest),
+            _ => Err("Must be either 'keep-oldest' or 'keep-newest'"),
+        }
+    }
+}
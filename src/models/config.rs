@@ -0,0 +1,132 @@
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use super::output::OutputFormat;
+use crate::error::AppError;
+
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// Root used when neither the command, `--root-dir` nor `ARK_ROOT`
+    /// provide one
+    pub root: Option<PathBuf>,
+    pub format: Option<String>,
+    /// Milliseconds between index updates in `monitor`
+    pub monitor_interval: Option<u64>,
+    /// Whether `link create` fetches the title and description
+    pub fetch_link_metadata: Option<bool>,
+    /// Minimal length of ids shown with `--short-ids`
+    pub short_id_min_len: Option<usize>,
+    /// Seconds into a video at which its thumbnail frame is taken
+    pub video_frame_at: Option<f64>,
+}
+
+impl Config {
+    pub const KEYS: [&'static str; 6] = [
+        "root",
+        "format",
+        "monitor_interval",
+        "fetch_link_metadata",
+        "short_id_min_len",
+        "video_frame_at",
+    ];
+
+    pub fn parse(data: &str) -> Result<Self, AppError> {
+        let config: Config = toml::from_str(data)
+            .map_err(|e| AppError::ConfigError(e.to_string()))?;
+        config.output_format()?;
+
+        Ok(config)
+    }
+
+    pub fn output_format(&self) -> Result<Option<OutputFormat>, AppError> {
+        self.format
+            .as_deref()
+            .map(|format| {
+                OutputFormat::from_str(format).map_err(|e| {
+                    AppError::ConfigError(format!("format: {}", e))
+                })
+            })
+            .transpose()
+    }
+
+    pub fn get(&self, key: &str) -> Result<Option<String>, AppError> {
+        Ok(match key {
+            "root" => self
+                .root
+                .as_ref()
+                .map(|root| root.display().to_string()),
+            "format" => self.format.clone(),
+            "monitor_interval" => self
+                .monitor_interval
+                .map(|interval| interval.to_string()),
+            "fetch_link_metadata" => self
+                .fetch_link_metadata
+                .map(|fetch| fetch.to_string()),
+            "short_id_min_len" => {
+                self.short_id_min_len.map(|len| len.to_string())
+            }
+            "video_frame_at" => self.video_frame_at.map(|at| at.to_string()),
+            _ => return Err(unknown_key(key)),
+        })
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), AppError> {
+        let invalid = |e: String| {
+            AppError::ConfigError(format!(
+                "{}: invalid value '{}', {}",
+                key, value, e
+            ))
+        };
+
+        match key {
+            "root" => self.root = Some(PathBuf::from(value)),
+            "format" => {
+                OutputFormat::from_str(value)
+                    .map_err(|e| invalid(e.to_owned()))?;
+                self.format = Some(value.to_lowercase());
+            }
+            "monitor_interval" => {
+                self.monitor_interval =
+                    Some(value.parse().map_err(|_| {
+                        invalid("expected milliseconds".to_owned())
+                    })?)
+            }
+            "fetch_link_metadata" => {
+                self.fetch_link_metadata =
+                    Some(value.parse().map_err(|_| {
+                        invalid("expected 'true' or 'false'".to_owned())
+                    })?)
+            }
+            "short_id_min_len" => {
+                self.short_id_min_len = Some(value.parse().map_err(|_| {
+                    invalid("expected a number of characters".to_owned())
+                })?)
+            }
+            "video_frame_at" => {
+                let at: f64 = value.parse().map_err(|_| {
+                    invalid("expected a number of seconds".to_owned())
+                })?;
+                if at.is_sign_negative() || !at.is_finite() {
+                    return Err(invalid(
+                        "expected a number of seconds".to_owned(),
+                    ));
+                }
+                self.video_frame_at = Some(at);
+            }
+            _ => return Err(unknown_key(key)),
+        }
+
+        Ok(())
+    }
+}
+
+fn unknown_key(key: &str) -> AppError {
+    AppError::ConfigError(format!(
+        "unknown key '{}', expected one of: {}",
+        key,
+        Config::KEYS.join(", ")
+    ))
+}
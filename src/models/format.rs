@@ -18,6 +18,15 @@ impl std::str::FromStr for Format {
     }
 }
 
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Format::KeyValue => write!(f, "json"),
+            Format::Raw => write!(f, "raw"),
+        }
+    }
+}
+
 pub fn key_value_to_str(
     s: &str,
 ) -> Result<Vec<(String, String)>, InlineJsonParseError> {
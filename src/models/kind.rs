@@ -0,0 +1,167 @@
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use clap::Parser;
+
+#[derive(Parser, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Image,
+    Video,
+    Audio,
+    Document,
+    Link,
+    Archive,
+    Other,
+}
+
+impl std::str::FromStr for Kind {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "image" => Ok(Kind::Image),
+            "video" => Ok(Kind::Video),
+            "audio" => Ok(Kind::Audio),
+            "document" => Ok(Kind::Document),
+            "link" => Ok(Kind::Link),
+            "archive" => Ok(Kind::Archive),
+            "other" => Ok(Kind::Other),
+            _ => Err("Kind must be one of 'image', 'video', 'audio', \
+                'document', 'link', 'archive' or 'other'"),
+        }
+    }
+}
+
+impl std::fmt::Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Kind::Image => "image",
+            Kind::Video => "video",
+            Kind::Audio => "audio",
+            Kind::Document => "document",
+            Kind::Link => "link",
+            Kind::Archive => "archive",
+            Kind::Other => "other",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+const LINK_MIME: &str = "application/x-ark-link";
+
+const EXTENSIONS: [(&str, &str); 34] = [
+    ("link", LINK_MIME),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("bmp", "image/bmp"),
+    ("svg", "image/svg+xml"),
+    ("heic", "image/heic"),
+    ("mp4", "video/mp4"),
+    ("m4v", "video/mp4"),
+    ("mkv", "video/x-matroska"),
+    ("webm", "video/webm"),
+    ("mov", "video/quicktime"),
+    ("avi", "video/x-msvideo"),
+    ("mp3", "audio/mpeg"),
+    ("flac", "audio/flac"),
+    ("ogg", "audio/ogg"),
+    ("wav", "audio/wav"),
+    ("m4a", "audio/mp4"),
+    ("pdf", "application/pdf"),
+    ("txt", "text/plain"),
+    ("md", "text/markdown"),
+    ("html", "text/html"),
+    ("epub", "application/epub+zip"),
+    ("doc", "application/msword"),
+    (
+        "docx",
+        "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+    ),
+    ("odt", "application/vnd.oasis.opendocument.text"),
+    ("zip", "application/zip"),
+    ("tar", "application/x-tar"),
+    ("gz", "application/gzip"),
+    ("zst", "application/zstd"),
+    ("7z", "application/x-7z-compressed"),
+    ("rar", "application/vnd.rar"),
+];
+
+/// Leading bytes of formats commonly stored without an extension
+const SIGNATURES: [(&[u8], &str); 7] = [
+    (b"\x89PNG", "image/png"),
+    (b"\xFF\xD8\xFF", "image/jpeg"),
+    (b"GIF8", "image/gif"),
+    (b"%PDF", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1F\x8B", "application/gzip"),
+    (b"\x1A\x45\xDF\xA3", "video/x-matroska"),
+];
+
+/// Guesses the MIME type by the extension,
+/// or by the first bytes of the file if the extension is unknown
+pub fn mime_type(path: &Path) -> Option<&'static str> {
+    let extension = path
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase());
+    if let Some(extension) = extension {
+        let known = EXTENSIONS
+            .iter()
+            .find(|(ext, _)| *ext == extension)
+            .map(|(_, mime)| *mime);
+        if known.is_some() {
+            return known;
+        }
+    }
+
+    let mut head = [0; 12];
+    let read = File::open(path).ok()?.read(&mut head).ok()?;
+    let head = &head[..read];
+    // MP4 and QuickTime files start with the size of the `ftyp` box
+    if head.len() >= 8 && &head[4..8] == b"ftyp" {
+        return Some("video/mp4");
+    }
+
+    SIGNATURES
+        .iter()
+        .find(|(signature, _)| head.starts_with(signature))
+        .map(|(_, mime)| *mime)
+}
+
+impl Kind {
+    pub fn from_mime(mime: &str) -> Kind {
+        let archives = [
+            "application/zip",
+            "application/x-tar",
+            "application/gzip",
+            "application/zstd",
+            "application/x-7z-compressed",
+            "application/vnd.rar",
+        ];
+
+        if mime == LINK_MIME {
+            Kind::Link
+        } else if archives.contains(&mime) {
+            Kind::Archive
+        } else if mime.starts_with("image/") {
+            Kind::Image
+        } else if mime.starts_with("video/") {
+            Kind::Video
+        } else if mime.starts_with("audio/") {
+            Kind::Audio
+        } else if mime.starts_with("text/") || mime.starts_with("application/")
+        {
+            Kind::Document
+        } else {
+            Kind::Other
+        }
+    }
+
+    pub fn of(path: &Path) -> Kind {
+        mime_type(path).map_or(Kind::Other, Kind::from_mime)
+    }
+}
@@ -0,0 +1,22 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ManifestFormat {
+    Hashdeep,
+    Sha256sum,
+    GitAnnex,
+}
+
+impl std::str::FromStr for ManifestFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "hashdeep" => Ok(ManifestFormat::Hashdeep),
+            "sha256sum" => Ok(ManifestFormat::Sha256sum),
+            "git-annex" => Ok(ManifestFormat::GitAnnex),
+            _ => Err(
+                "Manifest format must be either 'hashdeep', 'sha256sum' or \
+                 'git-annex'",
+            ),
+        }
+    }
+}
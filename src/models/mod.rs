@@ -1,5 +1,12 @@
+pub mod backup;
 pub mod cli;
+pub mod collisions;
+pub mod config;
 pub mod entry;
 pub mod format;
+pub mod kind;
+pub mod manifest;
+pub mod output;
+pub mod render;
 pub mod sort;
 pub mod storage;
@@ -0,0 +1,26 @@
+use clap::Parser;
+
+#[derive(Parser, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    /// Only supported by `list`, other commands print text instead
+    Csv,
+    Tsv,
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            "tsv" => Ok(OutputFormat::Tsv),
+            _ => Err(
+                "Output format must be one of 'text', 'json', 'csv' or 'tsv'",
+            ),
+        }
+    }
+}
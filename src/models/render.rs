@@ -0,0 +1,39 @@
+use image::ImageFormat;
+
+#[derive(Debug, Clone, Copy)]
+pub enum RenderFormat {
+    Png,
+    Jpeg,
+    Webp,
+}
+
+impl RenderFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            RenderFormat::Png => "png",
+            RenderFormat::Jpeg => "jpg",
+            RenderFormat::Webp => "webp",
+        }
+    }
+
+    pub fn image_format(&self) -> ImageFormat {
+        match self {
+            RenderFormat::Png => ImageFormat::Png,
+            RenderFormat::Jpeg => ImageFormat::Jpeg,
+            RenderFormat::Webp => ImageFormat::WebP,
+        }
+    }
+}
+
+impl std::str::FromStr for RenderFormat {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "png" => Ok(RenderFormat::Png),
+            "jpeg" | "jpg" => Ok(RenderFormat::Jpeg),
+            "webp" => Ok(RenderFormat::Webp),
+            _ => Err("Output format must be either 'png', 'jpeg' or 'webp'"),
+        }
+    }
+}
@@ -17,3 +17,26 @@ impl std::str::FromStr for Sort {
         }
     }
 }
+
+#[derive(Parser, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Modified,
+    Size,
+    /// The value of `--metadata-key`
+    Metadata,
+}
+
+impl std::str::FromStr for SortKey {
+    type Err = &'static str;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "modified" => Ok(SortKey::Modified),
+            "size" => Ok(SortKey::Size),
+            "metadata" => Ok(SortKey::Metadata),
+            _ => {
+                Err("Sort key must be either 'modified', 'size' or 'metadata'")
+            }
+        }
+    }
+}
@@ -1,6 +1,10 @@
-use arklib::{id::ResourceId, AtomicFile};
+use arklib::{id::ResourceId, modify, AtomicFile};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
 use std::fmt::Write;
+use std::io::{BufRead, BufReader};
 use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
 
 use crate::{
     commands::{
@@ -29,6 +33,50 @@ impl std::str::FromStr for StorageType {
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncPreference {
+    A,
+    B,
+    Newer,
+    Fail,
+}
+
+impl std::str::FromStr for SyncPreference {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "a" => Ok(SyncPreference::A),
+            "b" => Ok(SyncPreference::B),
+            "newer" => Ok(SyncPreference::Newer),
+            "fail" => Ok(SyncPreference::Fail),
+            _ => Err(format!("Invalid sync preference: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeStrategy {
+    KeepDst,
+    KeepSrc,
+    HigherVersion,
+    Newer,
+}
+
+impl std::str::FromStr for MergeStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "keep-dst" => Ok(MergeStrategy::KeepDst),
+            "keep-src" => Ok(MergeStrategy::KeepSrc),
+            "higher-version" => Ok(MergeStrategy::HigherVersion),
+            "newer" => Ok(MergeStrategy::Newer),
+            _ => Err(format!("Invalid merge strategy: {}", s)),
+        }
+    }
+}
+
 pub struct Storage {
     path: PathBuf,
     storage_type: StorageType,
@@ -58,6 +106,25 @@ impl Storage {
         })
     }
 
+    /// File-based storages keep a value per line, so a newline would
+    /// split it into a broken entry
+    pub fn check_value(
+        &self,
+        id: ResourceId,
+        content: &str,
+    ) -> Result<(), AppError> {
+        if matches!(self.storage_type, StorageType::File)
+            && content.contains(['\n', '\r'])
+        {
+            return Err(AppError::InvalidInput(format!(
+                "Values of file-based storages can't contain newlines: {}",
+                id
+            )));
+        }
+
+        Ok(())
+    }
+
     #[allow(dead_code)]
     pub fn load(&mut self) -> Result<(), AppError> {
         match self.storage_type {
@@ -122,6 +189,8 @@ impl Storage {
         content: &str,
         format: Format,
     ) -> Result<(), AppError> {
+        self.check_value(id, content)?;
+
         match self.storage_type {
             StorageType::File => {
                 let atomic_file = AtomicFile::new(&self.path).map_err(|e| {
@@ -223,18 +292,12 @@ impl Storage {
                     }
                 }
 
-                Err(AppError::StorageNotFound(format!(
-                    "Resource with id {} not found",
-                    id
-                )))
+                Err(AppError::ResourceNotFound(id))
             }
             StorageType::Folder => {
                 let folder_path = self.path.join(id.to_string());
                 if !folder_path.exists() {
-                    return Err(AppError::StorageNotFound(format!(
-                        "Resource with id {} not found",
-                        id
-                    )));
+                    return Err(AppError::ResourceNotFound(id));
                 }
 
                 let atomic_file = AtomicFile::new(&folder_path)
@@ -263,12 +326,47 @@ impl Storage {
         }
     }
 
+    /// Writes the value into `output` without loading it into memory,
+    /// only folder-based storages can hold values big enough to matter
+    pub fn read_to<W: std::io::Write>(
+        &mut self,
+        id: ResourceId,
+        output: &mut W,
+    ) -> Result<u64, AppError> {
+        match self.storage_type {
+            StorageType::File => {
+                let value = self.read(id)?;
+                output.write_all(value.as_bytes())?;
+                Ok(value.len() as u64)
+            }
+            StorageType::Folder => {
+                let folder_path = self.path.join(id.to_string());
+                if !folder_path.exists() {
+                    return Err(AppError::ResourceNotFound(id));
+                }
+
+                let atomic_file = AtomicFile::new(&folder_path)?;
+                let current = atomic_file.load().map_err(|e| {
+                    AppError::FileOperationError(format!(
+                        "Failed to load atomic file at {:?} with error: {:?}",
+                        folder_path, e
+                    ))
+                })?;
+
+                let mut file = std::fs::File::open(current.path)?;
+                Ok(std::io::copy(&mut file, output)?)
+            }
+        }
+    }
+
     pub fn insert(
         &mut self,
         id: ResourceId,
         content: &str,
         format: Format,
     ) -> Result<(), AppError> {
+        self.check_value(id, content)?;
+
         match self.storage_type {
             StorageType::File => {
                 let atomic_file = AtomicFile::new(&self.path).map_err(|e| {
@@ -289,14 +387,17 @@ impl Storage {
                     Format::Raw => format!("{}:{}\n", id, content),
                 };
 
-                match commands::file::file_insert(
-                    &atomic_file,
-                    &content,
-                    Format::Raw,
-                ) {
-                    Ok(_) => Ok(()),
-                    Err(e) => Err(e),
-                }
+                // Replace only the entry of this resource, keeping the others
+                let prefix = format!("{}:", id);
+                Ok(modify(&atomic_file, |current| {
+                    let mut data = String::from_utf8_lossy(current)
+                        .lines()
+                        .filter(|line| !line.starts_with(&prefix))
+                        .map(|line| format!("{}\n", line))
+                        .collect::<String>();
+                    data.push_str(&content);
+                    data.into_bytes()
+                })?)
             }
             StorageType::Folder => {
                 let folder_path = self.path.join(id.to_string());
@@ -326,6 +427,143 @@ impl Storage {
         }
     }
 
+    /// Collects writes in memory to apply them together, see [`StorageBatch`]
+    pub fn begin_batch(&mut self) -> StorageBatch<'_> {
+        StorageBatch {
+            storage: self,
+            pending: Vec::new(),
+            snapshot: None,
+            versions: HashMap::new(),
+            on_conflict: MergeStrategy::Newer,
+            started: Instant::now(),
+            max_entries: None,
+            max_age: None,
+        }
+    }
+
+    pub fn entries(&mut self) -> Result<Vec<(ResourceId, String)>, AppError> {
+        match self.storage_type {
+            StorageType::File => {
+                let atomic_file = AtomicFile::new(&self.path)?;
+                let data = atomic_file.load()?.read_to_string()?;
+
+                let mut entries: Vec<(ResourceId, String)> = Vec::new();
+                let mut seen = HashSet::new();
+                for (i, line) in data.lines().enumerate() {
+                    let parsed =
+                        line.split_once(':').and_then(|(id, value)| {
+                            Some((id.parse::<ResourceId>().ok()?, value))
+                        });
+
+                    match parsed {
+                        // The first value of an id wins, same as in `read`
+                        Some((id, value)) => {
+                            if seen.insert(id) {
+                                entries.push((id, value.to_owned()));
+                            }
+                        }
+                        None => eprintln!("Error parsing line {}", i),
+                    }
+                }
+
+                Ok(entries)
+            }
+            StorageType::Folder => {
+                self.files.clear();
+                self.load()?;
+
+                let ids = self.files.clone();
+                let mut entries = Vec::new();
+                for id in ids {
+                    entries.push((id, self.read(id)?));
+                }
+
+                Ok(entries)
+            }
+        }
+    }
+
+    /// Sizes of the values in bytes, measured without keeping them in memory:
+    /// file-based storages are read line by line, folder-based ones aren't read
+    pub fn value_sizes(&mut self) -> Result<Vec<(ResourceId, u64)>, AppError> {
+        match self.storage_type {
+            StorageType::File => {
+                let current = AtomicFile::new(&self.path)?.load()?;
+                let file = match std::fs::File::open(&current.path) {
+                    Ok(file) => file,
+                    // Nothing was written to the storage yet
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                        return Ok(Vec::new())
+                    }
+                    Err(e) => return Err(e.into()),
+                };
+
+                let mut sizes = Vec::new();
+                let mut seen = HashSet::new();
+                for line in BufReader::new(file).split(b'\n') {
+                    let line = line?;
+                    let Some(colon) = line.iter().position(|&b| b == b':')
+                    else {
+                        continue;
+                    };
+                    let id = std::str::from_utf8(&line[..colon])
+                        .ok()
+                        .and_then(|id| id.parse::<ResourceId>().ok());
+
+                    // The first value of an id wins, same as in `entries`
+                    if let Some(id) = id.filter(|id| seen.insert(*id)) {
+                        let value = &line[colon + 1..];
+                        let value = value.strip_suffix(b"\r").unwrap_or(value);
+                        sizes.push((id, value.len() as u64));
+                    }
+                }
+
+                Ok(sizes)
+            }
+            StorageType::Folder => {
+                self.files.clear();
+                self.load()?;
+
+                let mut sizes = Vec::new();
+                for id in self.files.clone() {
+                    let current =
+                        AtomicFile::new(self.path.join(id.to_string()))?
+                            .load()?;
+                    let size = std::fs::metadata(current.path)?.len();
+                    sizes.push((id, size));
+                }
+
+                Ok(sizes)
+            }
+        }
+    }
+
+    /// Time of the latest write of the value,
+    /// file-based storages can only tell it for the whole storage
+    pub fn modified(&self, id: ResourceId) -> Option<SystemTime> {
+        let path = match self.storage_type {
+            StorageType::File => self.path.clone(),
+            StorageType::Folder => self.path.join(id.to_string()),
+        };
+
+        let current = AtomicFile::new(path).ok()?.load().ok()?;
+        std::fs::metadata(current.path)
+            .ok()?
+            .modified()
+            .ok()
+    }
+
+    /// Version of the value as shown by `list(true)`,
+    /// file-based storages can only tell it for the whole storage
+    pub fn version(&self, id: ResourceId) -> Option<usize> {
+        let path = match self.storage_type {
+            StorageType::File => self.path.clone(),
+            StorageType::Folder => self.path.join(id.to_string()),
+        };
+
+        Some(AtomicFile::new(path).ok()?.load().ok()?.version)
+    }
+
     pub fn list(&self, versions: bool) -> Result<String, AppError> {
         let mut output = String::new();
 
@@ -433,3 +671,413 @@ impl Storage {
         Ok(output)
     }
 }
+
+enum BatchOp {
+    Insert(String),
+    Remove,
+}
+
+/// Writes and removals applied to a storage on `commit` with a single write
+/// of a file-based storage and a single write per value of a folder-based
+/// storage, however many times the value was changed in the batch.
+///
+/// Values changed by somebody else since the batch started are kept when
+/// the batch doesn't touch them; the ones it does touch are resolved with
+/// the merge strategy set by `on_conflict`, by default the later write wins.
+/// Writes not committed yet are lost if the process dies
+/// and discarded when the batch is dropped.
+pub struct StorageBatch<'a> {
+    storage: &'a mut Storage,
+    /// The latest write of each value with the time it was made,
+    /// in the order the values were first written
+    pending: Vec<(ResourceId, BatchOp, SystemTime)>,
+    /// Values of a file-based storage when the pending writes started
+    snapshot: Option<HashMap<ResourceId, String>>,
+    /// Versions of the values of a folder-based storage
+    /// when the pending writes to them started
+    versions: HashMap<ResourceId, Option<usize>>,
+    on_conflict: MergeStrategy,
+    started: Instant,
+    max_entries: Option<usize>,
+    max_age: Option<Duration>,
+}
+
+impl StorageBatch<'_> {
+    /// Commits the pending writes when `max_entries` values are waiting
+    /// or when the oldest of them has been waiting for `max_age`
+    pub fn auto_flush(
+        mut self,
+        max_entries: Option<usize>,
+        max_age: Option<Duration>,
+    ) -> Self {
+        self.max_entries = max_entries;
+        self.max_age = max_age;
+        self
+    }
+
+    /// How to resolve a value changed both by the batch and by somebody
+    /// else: `keep-src` keeps the batch write, `keep-dst` and
+    /// `higher-version` keep the other one, `newer` keeps the later one
+    pub fn on_conflict(mut self, strategy: MergeStrategy) -> Self {
+        self.on_conflict = strategy;
+        self
+    }
+
+    pub fn insert(
+        &mut self,
+        id: ResourceId,
+        content: &str,
+    ) -> Result<(), AppError> {
+        self.push(id, BatchOp::Insert(content.to_owned()))
+    }
+
+    pub fn remove(&mut self, id: ResourceId) -> Result<(), AppError> {
+        self.push(id, BatchOp::Remove)
+    }
+
+    /// Applies the pending writes, returns how many values they changed
+    pub fn commit(mut self) -> Result<usize, AppError> {
+        self.flush()
+    }
+
+    fn push(&mut self, id: ResourceId, op: BatchOp) -> Result<(), AppError> {
+        if let BatchOp::Insert(content) = &op {
+            self.storage.check_value(id, content)?;
+        }
+        if self.pending.is_empty() {
+            self.started = Instant::now();
+        }
+
+        match self.storage.storage_type {
+            StorageType::File => {
+                if self.snapshot.is_none() {
+                    let entries = self.storage.entries()?;
+                    self.snapshot = Some(entries.into_iter().collect());
+                }
+            }
+            StorageType::Folder => {
+                if !self.versions.contains_key(&id) {
+                    let version = self.storage.version(id);
+                    self.versions.insert(id, version);
+                }
+            }
+        }
+
+        let now = SystemTime::now();
+        match self
+            .pending
+            .iter_mut()
+            .find(|(pending, ..)| *pending == id)
+        {
+            Some(write) => *write = (id, op, now),
+            None => self.pending.push((id, op, now)),
+        }
+
+        let full = self
+            .max_entries
+            .is_some_and(|max| self.pending.len() >= max);
+        let stale = self
+            .max_age
+            .is_some_and(|max| self.started.elapsed() >= max);
+        if full || stale {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the batch write made at `written` should replace
+    /// a value somebody else changed at `changed`
+    fn batch_wins(
+        &self,
+        written: SystemTime,
+        changed: Option<SystemTime>,
+    ) -> bool {
+        match self.on_conflict {
+            MergeStrategy::KeepSrc => true,
+            MergeStrategy::KeepDst | MergeStrategy::HigherVersion => false,
+            MergeStrategy::Newer => {
+                changed.map_or(true, |changed| written >= changed)
+            }
+        }
+    }
+
+    fn report_conflict(&self, id: ResourceId, kept: bool) {
+        if !crate::util::is_quiet() {
+            eprintln!(
+                "Value of {} was changed during the batch, keeping the {} one",
+                id,
+                if kept {
+                    "batch"
+                } else {
+                    "other"
+                }
+            );
+        }
+    }
+
+    fn flush(&mut self) -> Result<usize, AppError> {
+        let pending = std::mem::take(&mut self.pending);
+        let snapshot = self.snapshot.take().unwrap_or_default();
+        let versions = std::mem::take(&mut self.versions);
+        let count = pending.len();
+        if count == 0 {
+            return Ok(0);
+        }
+
+        match self.storage.storage_type {
+            StorageType::File => {
+                let atomic_file = AtomicFile::new(&self.storage.path)?;
+                let changed = self.storage.modified(pending[0].0);
+                // Rebuilt on every attempt, `modify` retries on top of
+                // writes made by somebody else while it was applying ours
+                let conflicts = RefCell::new(Vec::new());
+                modify(&atomic_file, |current| {
+                    let mut conflicts = conflicts.borrow_mut();
+                    conflicts.clear();
+
+                    let mut lines = String::from_utf8_lossy(current)
+                        .lines()
+                        .map(|line| line.to_owned())
+                        .collect::<Vec<_>>();
+
+                    for (id, op, written) in &pending {
+                        let prefix = format!("{}:", id);
+                        let latest = lines
+                            .iter()
+                            .find_map(|line| line.strip_prefix(&prefix));
+                        if latest != snapshot.get(id).map(String::as_str) {
+                            let kept = self.batch_wins(*written, changed);
+                            conflicts.push((*id, kept));
+                            if !kept {
+                                continue;
+                            }
+                        }
+
+                        lines.retain(|line| !line.starts_with(&prefix));
+                        if let BatchOp::Insert(content) = op {
+                            lines.push(format!("{}{}", prefix, content));
+                        }
+                    }
+
+                    lines
+                        .into_iter()
+                        .map(|line| format!("{}\n", line))
+                        .collect::<String>()
+                        .into_bytes()
+                })?;
+
+                for (id, kept) in conflicts.into_inner() {
+                    self.report_conflict(id, kept);
+                }
+            }
+            StorageType::Folder => {
+                for (id, op, written) in pending {
+                    let base = versions.get(&id).copied().flatten();
+                    if self.storage.version(id) != base {
+                        let kept =
+                            self.batch_wins(written, self.storage.modified(id));
+                        self.report_conflict(id, kept);
+                        if !kept {
+                            continue;
+                        }
+                    }
+
+                    match op {
+                        BatchOp::Insert(content) => {
+                            self.storage.insert(id, &content, Format::Raw)?
+                        }
+                        BatchOp::Remove => {
+                            let folder = self.storage.path.join(id.to_string());
+                            if folder.exists() {
+                                std::fs::remove_dir_all(folder)?;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn id(n: u64) -> ResourceId {
+        format!("{}-{}", n, n).parse().unwrap()
+    }
+
+    fn open(dir: &TempDir, storage_type: StorageType) -> Storage {
+        Storage::new(dir.path().join("storage"), storage_type).unwrap()
+    }
+
+    fn sorted(
+        mut entries: Vec<(ResourceId, String)>,
+    ) -> Vec<(ResourceId, String)> {
+        entries.sort_by_key(|(id, _)| id.to_string());
+        entries
+    }
+
+    /// Lets the batch writes made so far be older than the next ones
+    /// even with a coarse modification time
+    fn tick() {
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    #[test]
+    fn batch_writes_file_storage_once() {
+        let batched_dir = TempDir::new().unwrap();
+        let mut batched = open(&batched_dir, StorageType::File);
+        let unbatched_dir = TempDir::new().unwrap();
+        let mut unbatched = open(&unbatched_dir, StorageType::File);
+
+        let before = batched.version(id(0)).unwrap_or(0);
+        let mut batch = batched.begin_batch();
+        for n in 0..50 {
+            batch
+                .insert(id(n), &format!("value {}", n))
+                .unwrap();
+        }
+        assert_eq!(batch.commit().unwrap(), 50);
+        assert_eq!(batched.version(id(0)).unwrap_or(0), before + 1);
+
+        let before = unbatched.version(id(0)).unwrap_or(0);
+        for n in 0..50 {
+            unbatched
+                .insert(id(n), &format!("value {}", n), Format::Raw)
+                .unwrap();
+        }
+        assert_eq!(unbatched.version(id(0)).unwrap_or(0), before + 50);
+
+        assert_eq!(
+            sorted(batched.entries().unwrap()),
+            sorted(unbatched.entries().unwrap())
+        );
+    }
+
+    #[test]
+    fn batch_writes_folder_value_once() {
+        let dir = TempDir::new().unwrap();
+        let mut storage = open(&dir, StorageType::Folder);
+
+        storage
+            .insert(id(0), "single", Format::Raw)
+            .unwrap();
+        let single = storage.version(id(0));
+
+        for n in 0..5 {
+            storage
+                .insert(id(1), &format!("unbatched {}", n), Format::Raw)
+                .unwrap();
+        }
+
+        let mut batch = storage.begin_batch();
+        for n in 0..5 {
+            batch
+                .insert(id(2), &format!("batched {}", n))
+                .unwrap();
+        }
+        assert_eq!(batch.commit().unwrap(), 1);
+
+        assert_eq!(storage.version(id(2)), single);
+        assert!(storage.version(id(1)) > single);
+        assert_eq!(storage.read(id(1)).unwrap(), "unbatched 4");
+        assert_eq!(storage.read(id(2)).unwrap(), "batched 4");
+    }
+
+    #[test]
+    fn batch_flushes_by_count() {
+        let dir = TempDir::new().unwrap();
+        let mut storage = open(&dir, StorageType::File);
+        let mut other = open(&dir, StorageType::File);
+
+        let before = other.version(id(0)).unwrap_or(0);
+        let mut batch = storage.begin_batch().auto_flush(Some(3), None);
+        for n in 0..7 {
+            batch.insert(id(n), "value").unwrap();
+        }
+        // Writing a pending value again doesn't count twice
+        batch.insert(id(6), "value").unwrap();
+
+        assert_eq!(other.entries().unwrap().len(), 6);
+        assert_eq!(other.version(id(0)).unwrap_or(0), before + 2);
+
+        assert_eq!(batch.commit().unwrap(), 1);
+        assert_eq!(other.entries().unwrap().len(), 7);
+        assert_eq!(other.version(id(0)).unwrap_or(0), before + 3);
+    }
+
+    #[test]
+    fn batch_keeps_concurrent_file_edits() {
+        let dir = TempDir::new().unwrap();
+        let mut storage = open(&dir, StorageType::File);
+        let mut other = open(&dir, StorageType::File);
+        other
+            .insert(id(2), "initial", Format::Raw)
+            .unwrap();
+
+        let mut batch = storage.begin_batch();
+        batch.insert(id(1), "batch").unwrap();
+        batch.insert(id(2), "batch").unwrap();
+        tick();
+        other.insert(id(2), "other", Format::Raw).unwrap();
+        other.insert(id(3), "other", Format::Raw).unwrap();
+        batch.commit().unwrap();
+
+        // The other write of 2 is the later one
+        assert_eq!(
+            sorted(other.entries().unwrap()),
+            vec![
+                (id(1), "batch".to_owned()),
+                (id(2), "other".to_owned()),
+                (id(3), "other".to_owned()),
+            ]
+        );
+
+        let mut batch = storage.begin_batch();
+        batch.insert(id(2), "batch").unwrap();
+        batch.remove(id(3)).unwrap();
+        tick();
+        other.insert(id(4), "other", Format::Raw).unwrap();
+        batch.commit().unwrap();
+
+        // Nobody else changed 2 and 3 this time
+        assert_eq!(
+            sorted(other.entries().unwrap()),
+            vec![
+                (id(1), "batch".to_owned()),
+                (id(2), "batch".to_owned()),
+                (id(4), "other".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn batch_resolves_conflicts_by_strategy() {
+        for storage_type in [StorageType::File, StorageType::Folder] {
+            for (strategy, expected) in [
+                (MergeStrategy::KeepSrc, "batch"),
+                (MergeStrategy::KeepDst, "other"),
+            ] {
+                let dir = TempDir::new().unwrap();
+                let mut storage = open(&dir, storage_type);
+                let mut other = open(&dir, storage_type);
+
+                let mut batch = storage.begin_batch().on_conflict(strategy);
+                batch.insert(id(1), "batch").unwrap();
+                batch.insert(id(2), "batch").unwrap();
+                other.insert(id(1), "other", Format::Raw).unwrap();
+                other.insert(id(3), "other", Format::Raw).unwrap();
+                batch.commit().unwrap();
+
+                assert_eq!(other.read(id(1)).unwrap(), expected);
+                assert_eq!(other.read(id(2)).unwrap(), "batch");
+                assert_eq!(other.read(id(3)).unwrap(), "other");
+            }
+        }
+    }
+}
@@ -5,102 +5,420 @@ use arklib::{
     PROPERTIES_STORAGE_FOLDER, SCORE_STORAGE_FILE, STATS_FOLDER,
     TAG_STORAGE_FILE, THUMBNAILS_STORAGE_FOLDER,
 };
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, NaiveDate, Utc};
+use glob::{MatchOptions, Pattern};
+use home::home_dir;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::collections::HashMap;
 use std::env::current_dir;
 use std::fs::{canonicalize, metadata};
-use std::io::BufRead;
 use std::io::BufReader;
+use std::io::{stderr, stdin, stdout, BufRead, IsTerminal, Read, Write};
 use std::path::Path;
 use std::str::FromStr;
+use std::sync::{mpsc, OnceLock};
 use std::thread;
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 use std::{fs::File, path::PathBuf};
 
+use crate::commands::id::parse_id;
+use crate::commands::{history, migrate, render};
 use crate::error::AppError;
+use crate::models::config::Config;
+use crate::models::output::OutputFormat;
 use crate::models::storage::{Storage, StorageType};
-use crate::ARK_CONFIG;
+use crate::{
+    ARK_BACKUPS_PATH, ARK_CONFIG, CONFIG_FILENAME, ROOTS_CFG_FILENAME,
+};
 
+/// The search is reported on stderr, so that it doesn't mix with the
+/// output of the command, unless quiet
 pub fn discover_roots(
     roots_cfg: &Option<PathBuf>,
 ) -> Result<Vec<PathBuf>, AppError> {
+    let report = |message: String| {
+        if !is_quiet() {
+            eprintln!("{}", message);
+        }
+    };
+
     if let Some(path) = roots_cfg {
-        println!(
+        report(format!(
             "\tRoots config provided explicitly:\n\t\t{}",
             path.display()
-        );
+        ));
         let config = File::open(path)?;
 
         Ok(parse_roots(config))
-    } else if let Ok(config) = File::open(ARK_CONFIG) {
-        println!(
+    } else if let Ok(config) = File::open(roots_config()?) {
+        report(format!(
             "\tRoots config was found automatically:\n\t\t{}",
-            &ARK_CONFIG
-        );
+            roots_config()?.display()
+        ));
 
         Ok(parse_roots(config))
     } else {
-        println!("\tRoots config wasn't found.");
+        report("\tRoots config wasn't found.".to_owned());
 
-        println!("Looking for a folder containing tag storage:");
+        report("Looking for a folder containing tag storage:".to_owned());
         let path =
             canonicalize(current_dir().expect("Can't open current directory!"))
                 .expect("Couldn't canonicalize working directory!");
 
         let result = path.ancestors().find(|path| {
-            println!("\t{}", path.display());
+            report(format!("\t{}", path.display()));
             storages_exists(path)
         });
 
         if let Some(root) = result {
-            println!("Root folder found:\n\t{}", root.display());
+            report(format!("Root folder found:\n\t{}", root.display()));
             Ok(vec![root.to_path_buf()])
         } else {
-            println!("Root folder wasn't found.");
+            report("Root folder wasn't found.".to_owned());
             Ok(vec![])
         }
     }
 }
 
+pub fn roots_config() -> Result<PathBuf, AppError> {
+    let config = home_dir()
+        .ok_or(AppError::HomeDirNotFound)?
+        .join(ARK_CONFIG);
+
+    // Older versions kept the roots right in this file
+    if config.is_file() {
+        Ok(config)
+    } else {
+        Ok(config.join(ROOTS_CFG_FILENAME))
+    }
+}
+
+pub fn config_path() -> Result<PathBuf, AppError> {
+    Ok(home_dir()
+        .ok_or(AppError::HomeDirNotFound)?
+        .join(ARK_CONFIG)
+        .join(CONFIG_FILENAME))
+}
+
+/// Missing config file means the defaults
+pub fn load_config() -> Result<Config, AppError> {
+    match std::fs::read_to_string(config_path()?) {
+        Ok(data) => Config::parse(&data),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            Ok(Config::default())
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub fn save_config(config: &Config) -> Result<(), AppError> {
+    let path = config_path()?;
+    let dir = path.parent().expect("Config path has a parent");
+    if dir.is_file() {
+        return Err(AppError::ConfigError(format!(
+            "{} is a roots file of an older version, move it to {}",
+            dir.display(),
+            dir.join(ROOTS_CFG_FILENAME).display()
+        )));
+    }
+    std::fs::create_dir_all(dir)?;
+
+    let data = toml::to_string(config)
+        .map_err(|e| AppError::ConfigError(e.to_string()))?;
+    Ok(std::fs::write(path, data)?)
+}
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// Config loaded at startup
+pub fn set_config(config: Config) {
+    let _ = CONFIG.set(config);
+}
+
+pub fn provide_config() -> Config {
+    CONFIG.get().cloned().unwrap_or_default()
+}
+
+/// Output format given to the command or the configured one
+pub fn output_format(format: &Option<OutputFormat>) -> Option<OutputFormat> {
+    format.or_else(|| provide_config().output_format().ok().flatten())
+}
+
+static DEFAULT_ROOT: OnceLock<PathBuf> = OnceLock::new();
+
+/// Root from `--root-dir` or `ARK_ROOT`, set once at startup.
+/// Takes precedence over the configured root.
+pub fn set_default_root(root: PathBuf) {
+    let _ = DEFAULT_ROOT.set(root);
+}
+
+/// The root is canonical, with `.`, `..` and symlinks resolved,
+/// like the paths in its index. Roots which don't exist yet are
+/// returned as given.
 pub fn provide_root(root_dir: &Option<PathBuf>) -> Result<PathBuf, AppError> {
-    if let Some(path) = root_dir {
+    let root = if let Some(path) = root_dir {
+        path.clone()
+    } else if let Some(path) = DEFAULT_ROOT.get() {
+        path.clone()
+    } else if let Some(path) = provide_config().root {
+        path
+    } else {
+        current_dir()?
+    };
+
+    Ok(canonicalize(&root).unwrap_or(root))
+}
+
+static QUIET: OnceLock<bool> = OnceLock::new();
+
+/// Whether `--quiet` was given, set once at startup
+pub fn set_quiet(quiet: bool) {
+    let _ = QUIET.set(quiet);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.get().copied().unwrap_or(false)
+}
+
+/// Operations finishing sooner don't show any progress
+const PROGRESS_DELAY: Duration = Duration::from_secs(1);
+
+/// Progress is drawn on stderr, so that it never mixes with the output,
+/// and only when stderr is a terminal and `--quiet` is not given.
+/// With a known length in bytes it is a bar, otherwise a spinner.
+pub fn progress_bar(message: &str, len: Option<u64>) -> ProgressBar {
+    if is_quiet() || !stderr().is_terminal() {
+        return ProgressBar::hidden();
+    }
+
+    let progress = match len {
+        Some(len) => ProgressBar::new(len).with_style(
+            ProgressStyle::with_template(
+                "{msg} {wide_bar} {bytes}/{total_bytes} ({eta})",
+            )
+            .expect("Progress template is valid"),
+        ),
+        None => ProgressBar::new_spinner().with_style(
+            ProgressStyle::with_template("{spinner} {msg} ({elapsed})")
+                .expect("Progress template is valid"),
+        ),
+    };
+    progress.set_draw_target(ProgressDrawTarget::hidden());
+    progress.set_message(message.to_owned());
+    progress.enable_steady_tick(Duration::from_millis(100));
+
+    let delayed = progress.clone();
+    thread::spawn(move || {
+        thread::sleep(PROGRESS_DELAY);
+        if !delayed.is_finished() {
+            delayed.set_draw_target(ProgressDrawTarget::stderr());
+        }
+    });
+
+    progress
+}
+
+/// Backups go to `~/.ark-backups` unless another location is given
+pub fn provide_backups_dir(
+    output: &Option<PathBuf>,
+) -> Result<PathBuf, AppError> {
+    if let Some(path) = output {
         Ok(path.clone())
     } else {
-        Ok(current_dir()?)
+        Ok(home_dir()
+            .ok_or(AppError::HomeDirNotFound)?
+            .join(ARK_BACKUPS_PATH))
     }
 }
 
 // Read-only structure
 pub fn provide_index(root_dir: &PathBuf) -> ResourceIndex {
+    let progress =
+        progress_bar(&format!("Indexing {}", root_dir.display()), None);
     let rwlock =
         arklib::provide_index(root_dir).expect("Failed to retrieve index");
+    progress.finish_and_clear();
     let index = &*rwlock.read().unwrap();
     index.clone()
 }
 
+/// Updates the indexes of the roots every `millis` milliseconds
+/// and returns the changes once there are any
+pub fn wait_for_changes(
+    roots: &[PathBuf],
+    millis: u64,
+) -> Result<Vec<IndexEvent>, AppError> {
+    loop {
+        thread::sleep(Duration::from_millis(millis));
+
+        let mut events = Vec::new();
+        for root in roots {
+            let rwlock = arklib::provide_index(root)
+                .map_err(|e| AppError::IndexError(e.to_string()))?;
+            let mut index = rwlock.write().map_err(|_| {
+                AppError::IndexError("Could not write index".to_owned())
+            })?;
+
+            let before = index_snapshot(&index);
+            index
+                .update_all()
+                .map_err(|e| AppError::IndexError(e.to_string()))?;
+            events.extend(index_events(&before, &index_snapshot(&index)));
+        }
+
+        if !events.is_empty() {
+            return Ok(events);
+        }
+    }
+}
+
 pub fn monitor_index(
     root_dir: &Option<PathBuf>,
     interval: Option<u64>,
+    json: bool,
+    record_history: bool,
+    migrate_metadata: bool,
+    migrate_window: Option<Duration>,
+    previews: bool,
 ) -> Result<(), AppError> {
     let dir_path = provide_root(root_dir)?;
+    let history_root = dir_path.clone();
+    let previews = previews.then(|| spawn_preview_worker(dir_path.clone()));
 
-    println!("Building index of folder {}", dir_path.display());
+    if !json {
+        println!("Building index of folder {}", dir_path.display());
+    }
     let start = Instant::now();
 
+    let progress =
+        progress_bar(&format!("Indexing {}", dir_path.display()), None);
     let result = arklib::provide_index(dir_path);
+    progress.finish_and_clear();
     let duration = start.elapsed();
 
     match result {
         Ok(rwlock) => {
-            println!("Build succeeded in {:?}\n", duration);
+            if !json {
+                println!("Build succeeded in {:?}\n", duration);
+            }
 
             if let Some(millis) = interval {
                 let mut index = rwlock.write().unwrap();
+                // Removed paths with their ids, a path added back within
+                // `migrate_window` gets the metadata of the removed one
+                let mut removed: HashMap<PathBuf, (ResourceId, Instant)> =
+                    HashMap::new();
                 loop {
                     let pause = Duration::from_millis(millis);
                     thread::sleep(pause);
 
+                    let track_events = json
+                        || record_history
+                        || migrate_metadata
+                        || previews.is_some();
+                    let before = if track_events {
+                        index_snapshot(&index)
+                    } else {
+                        HashMap::new()
+                    };
+
                     let start = Instant::now();
-                    match index.update_all() {
-                        Err(msg) => println!("Oops! {}", msg),
+                    let result = index.update_all();
+
+                    if result.is_ok() && track_events {
+                        let after = index_snapshot(&index);
+                        let events = index_events(&before, &after);
+
+                        let mut migrated = HashMap::new();
+                        if migrate_metadata {
+                            let mut changes = events
+                                .iter()
+                                .filter_map(|event| {
+                                    Some((event.old_id?, event.id))
+                                })
+                                .collect::<Vec<_>>();
+                            if let Some(window) = migrate_window {
+                                removed.retain(|_, (_, at)| {
+                                    at.elapsed() <= window
+                                });
+                                for event in &events {
+                                    match event.kind {
+                                        "removed" => {
+                                            removed.insert(
+                                                event.path.clone(),
+                                                (event.id, Instant::now()),
+                                            );
+                                        }
+                                        "added" => {
+                                            if let Some((old_id, _)) =
+                                                removed.remove(&event.path)
+                                            {
+                                                changes
+                                                    .push((old_id, event.id));
+                                            }
+                                        }
+                                        _ => {}
+                                    }
+                                }
+                            }
+
+                            for (old_id, new_id) in changes {
+                                match migrate::migrate_metadata(
+                                    &history_root,
+                                    &[(old_id, new_id)],
+                                ) {
+                                    Ok(count) => {
+                                        migrated.insert(new_id, count);
+                                    }
+                                    Err(e) => eprintln!(
+                                        "Could not migrate metadata of {}: {}",
+                                        old_id, e
+                                    ),
+                                }
+                            }
+                        }
+
+                        if let Some(previews) = &previews {
+                            events
+                                .iter()
+                                .filter(|event| event.kind != "removed")
+                                .for_each(|event| {
+                                    let _ = previews.send(event.path.clone());
+                                });
+                        }
+
+                        if record_history {
+                            if let Err(e) =
+                                history::record_events(&history_root, &events)
+                            {
+                                eprintln!("Could not record history: {}", e);
+                            }
+                        }
+                        if json {
+                            let lines = events.iter().map(|event| {
+                                let mut output = event.to_json();
+                                if let Some(count) = migrated.get(&event.id) {
+                                    output["migrated"] = (*count).into();
+                                }
+                                output.to_string()
+                            });
+                            if let Err(e) =
+                                write_lines(&mut stdout().lock(), lines)
+                            {
+                                // Nobody reads the events anymore, keep
+                                // what was indexed and stop
+                                index.store().expect("Could not store index");
+                                return Err(e.into());
+                            }
+                        }
+                    }
+
+                    match result {
+                        Err(msg) => eprintln!("Oops! {}", msg),
+                        Ok(_) if json => {
+                            index.store().expect("Could not store index");
+                        }
                         Ok(diff) => {
                             index.store().expect("Could not store index");
                             let duration = start.elapsed();
@@ -125,12 +443,213 @@ pub fn monitor_index(
                 }
             }
         }
-        Err(err) => println!("Failure: {:?}", err),
+        Err(err) => return Err(err.into()),
     }
 
     Ok(())
 }
 
+/// Renders previews of the sent paths into the cache of the root on
+/// a separate thread, so that monitoring doesn't wait for them
+fn spawn_preview_worker(root: PathBuf) -> mpsc::Sender<PathBuf> {
+    let (sender, receiver) = mpsc::channel::<PathBuf>();
+    thread::spawn(move || {
+        for path in receiver {
+            if !render::is_renderable(&path) {
+                eprintln!(
+                    "Skipping preview of {}: unsupported type",
+                    path.display()
+                );
+                continue;
+            }
+
+            match render::render_cached(&path, "high", &root, None, None) {
+                Ok((_, true)) => {}
+                Ok((preview, false)) => {
+                    if !is_quiet() {
+                        eprintln!(
+                            "Rendered preview of {} to {}",
+                            path.display(),
+                            preview.display()
+                        );
+                    }
+                }
+                Err(e) => eprintln!(
+                    "Could not render preview of {}: {}",
+                    path.display(),
+                    e
+                ),
+            }
+        }
+    });
+    sender
+}
+
+pub struct IndexEvent {
+    /// `added`, `removed` or `updated` when the path stayed,
+    /// but its content (and so its id) changed
+    pub kind: &'static str,
+    pub id: ResourceId,
+    pub old_id: Option<ResourceId>,
+    pub path: PathBuf,
+}
+
+impl IndexEvent {
+    pub fn to_json(&self) -> serde_json::Value {
+        let mut event = serde_json::json!({
+            "kind": self.kind,
+            "id": self.id.to_string(),
+            "path": self.path.display().to_string(),
+        });
+        if let Some(old_id) = self.old_id {
+            event["old_id"] = serde_json::Value::String(old_id.to_string());
+        }
+
+        event
+    }
+}
+
+pub fn index_snapshot(index: &ResourceIndex) -> HashMap<PathBuf, ResourceId> {
+    index
+        .path2id
+        .iter()
+        .map(|(path, entry)| (path.as_path().to_path_buf(), entry.id))
+        .collect()
+}
+
+pub fn index_events(
+    before: &HashMap<PathBuf, ResourceId>,
+    after: &HashMap<PathBuf, ResourceId>,
+) -> Vec<IndexEvent> {
+    let event = |kind, id: &ResourceId, old_id, path: &PathBuf| IndexEvent {
+        kind,
+        id: *id,
+        old_id,
+        path: path.to_owned(),
+    };
+
+    let mut events = Vec::new();
+    for (path, id) in after {
+        match before.get(path) {
+            None => events.push(event("added", id, None, path)),
+            Some(old) if old != id => {
+                events.push(event("updated", id, Some(*old), path))
+            }
+            Some(_) => {}
+        }
+    }
+    for (path, id) in before {
+        if !after.contains_key(path) {
+            events.push(event("removed", id, None, path));
+        }
+    }
+
+    events
+}
+
+/// Accepts dates (`2024-01-01`), RFC 3339 timestamps and durations
+/// relative to the current time (`30m`, `12h`, `7d`, `2w`)
+pub fn parse_time(s: &str) -> Result<DateTime<Utc>, AppError> {
+    let invalid = || AppError::InvalidTime(s.to_owned());
+
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time.with_timezone(&Utc));
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        let midnight = date.and_hms_opt(0, 0, 0).ok_or_else(invalid)?;
+        return Ok(DateTime::from_naive_utc_and_offset(midnight, Utc));
+    }
+
+    if s.len() < 2 || !s.is_char_boundary(s.len() - 1) {
+        return Err(invalid());
+    }
+    let (amount, unit) = s.split_at(s.len() - 1);
+    let amount = amount.parse::<i64>().map_err(|_| invalid())?;
+    // Durations too large for chrono are invalid rather than a panic
+    let duration = match unit {
+        "s" => chrono::Duration::try_seconds(amount),
+        "m" => chrono::Duration::try_minutes(amount),
+        "h" => chrono::Duration::try_hours(amount),
+        "d" => chrono::Duration::try_days(amount),
+        "w" => chrono::Duration::try_weeks(amount),
+        _ => return Err(invalid()),
+    }
+    .ok_or_else(invalid)?;
+
+    Utc::now()
+        .checked_sub_signed(duration)
+        .ok_or_else(invalid)
+}
+
+/// chrono only reports invalid strftime patterns while formatting
+pub fn validate_date_format(format: &str) -> Result<(), AppError> {
+    if StrftimeItems::new(format).any(|item| matches!(item, Item::Error)) {
+        return Err(AppError::InvalidDateFormat(format.to_owned()));
+    }
+
+    Ok(())
+}
+
+/// Rust ignores SIGPIPE, so writing into a closed pipe
+/// (e.g. `ark-cli list | head`) makes `println!` panic.
+/// Restoring the default handler terminates the process silently
+/// with the conventional exit code 141 and stops any further work.
+/// Where there is no SIGPIPE, streaming output goes through
+/// [`write_lines`], which stops at the first failed write.
+#[cfg(unix)]
+pub fn reset_sigpipe() {
+    // SAFETY: called first thing in `main`, before any other thread
+    // exists, and SIG_DFL is a valid disposition for SIGPIPE
+    unsafe {
+        libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+    }
+}
+
+#[cfg(not(unix))]
+pub fn reset_sigpipe() {}
+
+/// Writes the lines one by one, producing each only after the previous
+/// one was written, so that no more work is done once the output is
+/// closed. The failed write is returned, `BrokenPipe` for a closed pipe.
+pub fn write_lines<W, I>(output: &mut W, lines: I) -> std::io::Result<usize>
+where
+    W: Write,
+    I: IntoIterator<Item = String>,
+{
+    let mut written = 0;
+    for line in lines {
+        writeln!(output, "{}", line)?;
+        written += 1;
+    }
+    output.flush()?;
+
+    Ok(written)
+}
+
+/// Writes a header row and the rows as CSV, or TSV with a `\t` delimiter.
+/// Values holding the delimiter, quotes or newlines are quoted.
+pub fn write_table<W, R>(
+    output: W,
+    delimiter: u8,
+    columns: &[&str],
+    rows: R,
+) -> csv::Result<()>
+where
+    W: Write,
+    R: IntoIterator<Item = Vec<String>>,
+{
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(output);
+    writer.write_record(columns)?;
+    for row in rows {
+        writer.write_record(row)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
 pub fn storages_exists(path: &Path) -> bool {
     let meta = metadata(path.join(arklib::ARK_FOLDER));
     if let Ok(meta) = meta {
@@ -146,13 +665,126 @@ pub fn parse_roots(config: File) -> Vec<PathBuf> {
         .filter_map(|line| match line {
             Ok(path) => Some(PathBuf::from(path)),
             Err(msg) => {
-                println!("{:?}", msg);
+                eprintln!("{:?}", msg);
                 None
             }
         })
         .collect()
 }
 
+pub fn confirm(question: &str) -> Result<bool, AppError> {
+    print!("{} [y/N] ", question);
+    stdout().flush()?;
+
+    let mut answer = String::new();
+    stdin().lock().read_line(&mut answer)?;
+
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+/// The value itself, or the text read from stdin when it is `-` or omitted.
+/// Exactly one trailing newline of stdin is stripped, any other newline
+/// is rejected for file-based storages, which keep a value per line.
+pub fn read_content(
+    content: &Option<String>,
+    storage_type: StorageType,
+) -> Result<String, AppError> {
+    if let Some(content) = content.as_deref().filter(|c| *c != "-") {
+        return single_line(content.to_owned(), storage_type);
+    }
+
+    let mut bytes = Vec::new();
+    stdin().lock().read_to_end(&mut bytes)?;
+
+    let binary = || {
+        AppError::InvalidInput(
+            "stdin must be text, binary values can't be stored".to_owned(),
+        )
+    };
+    let mut content = String::from_utf8(bytes).map_err(|_| binary())?;
+    if content.contains('\0') {
+        return Err(binary());
+    }
+
+    if content.ends_with('\n') {
+        content.pop();
+        if content.ends_with('\r') {
+            content.pop();
+        }
+    }
+
+    single_line(content, storage_type)
+}
+
+fn single_line(
+    content: String,
+    storage_type: StorageType,
+) -> Result<String, AppError> {
+    if matches!(storage_type, StorageType::File)
+        && content.contains(['\n', '\r'])
+    {
+        return Err(AppError::InvalidInput(
+            "Values of file-based storages must be a single line".to_owned(),
+        ));
+    }
+
+    Ok(content)
+}
+
+pub fn compile_globs(patterns: &[String]) -> Result<Vec<Pattern>, AppError> {
+    patterns
+        .iter()
+        .map(|pattern| {
+            Pattern::new(pattern).map_err(|e| {
+                AppError::InvalidGlob(format!("{}: {}", pattern, e))
+            })
+        })
+        .collect()
+}
+
+/// `*` stays within one folder, `**` matches any depth
+pub fn glob_matches(globs: &[Pattern], path: &Path) -> bool {
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    };
+
+    globs
+        .iter()
+        .any(|glob| glob.matches_path_with(path, options))
+}
+
+/// Keeps the first `len` characters of a single-line preview
+pub fn truncate_preview(content: &str, len: usize) -> String {
+    let content = content
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    if content.chars().count() <= len {
+        return content;
+    }
+
+    let mut preview = content.chars().take(len).collect::<String>();
+    preview.push('…');
+    preview
+}
+
+/// Formats the size with binary units, e.g. `1.5 KiB`
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
 pub fn timestamp() -> Duration {
     let start = SystemTime::now();
     start
@@ -224,28 +856,170 @@ pub fn translate_storage(
     }
 }
 
+/// Path and type of the storage. Named storages have a known type,
+/// the type of other storages is `type_` if given, otherwise it is
+/// detected by their contents and falls back to a file-based storage.
+pub fn resolve_storage(
+    root: &PathBuf,
+    storage: &str,
+    type_: &Option<StorageType>,
+) -> Result<(PathBuf, StorageType), AppError> {
+    let (path, known) = translate_storage(&Some(root.to_owned()), storage)
+        .ok_or(AppError::StorageNotFound(storage.to_owned()))?;
+
+    let storage_type = known
+        .or(*type_)
+        .or_else(|| detect_storage_type(&path))
+        .unwrap_or(StorageType::File);
+
+    Ok((path, storage_type))
+}
+
+/// A folder-based storage keeps a directory for every value,
+/// a file-based one is a single atomic file holding only versions
+fn detect_storage_type(path: &Path) -> Option<StorageType> {
+    let mut entries = std::fs::read_dir(path)
+        .ok()?
+        .filter_map(Result::ok);
+    let first = entries.next()?;
+
+    if first.file_type().ok()?.is_dir() {
+        Some(StorageType::Folder)
+    } else {
+        Some(StorageType::File)
+    }
+}
+
 pub fn read_storage_value(
     root_dir: &PathBuf,
     storage: &str,
     id: &str,
     type_: &Option<String>,
 ) -> Result<String, AppError> {
-    let (file_path, storage_type) =
-        translate_storage(&Some(root_dir.to_owned()), storage)
-            .ok_or(AppError::StorageNotFound(storage.to_owned()))?;
-
-    let storage_type = storage_type.unwrap_or(match type_ {
-        Some(type_) => match type_.to_lowercase().as_str() {
-            "file" => StorageType::File,
-            "folder" => StorageType::Folder,
-            _ => panic!("unknown storage type"),
-        },
-        None => StorageType::File,
-    });
+    let type_ = type_
+        .as_deref()
+        .map(StorageType::from_str)
+        .transpose()
+        .map_err(AppError::InvalidOptions)?;
+    let (file_path, storage_type) = resolve_storage(root_dir, storage, &type_)?;
 
     let mut storage = Storage::new(file_path, storage_type)?;
 
-    let resource_id = ResourceId::from_str(id)?;
+    let resource_id = parse_id(id)?;
 
     storage.read(resource_id)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_time_relative_and_absolute() {
+        let hour_ago = parse_time("1h").unwrap();
+        let elapsed = Utc::now() - hour_ago;
+        assert!(elapsed >= chrono::Duration::hours(1));
+        assert!(
+            elapsed < chrono::Duration::hours(1) + chrono::Duration::minutes(1)
+        );
+
+        assert_eq!(
+            parse_time("2024-01-02T03:04:05Z")
+                .unwrap()
+                .timestamp(),
+            1704164645
+        );
+    }
+
+    /// Accepts `capacity` lines, then fails like a pipe closed by its reader
+    struct ClosingPipe {
+        capacity: usize,
+        lines: usize,
+    }
+
+    impl Write for ClosingPipe {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.lines == self.capacity {
+                return Err(std::io::ErrorKind::BrokenPipe.into());
+            }
+            self.lines += buf.iter().filter(|&&byte| byte == b'\n').count();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn write_lines_stops_producing_when_the_pipe_closes() {
+        let produced = std::cell::Cell::new(0);
+        let lines = (0..1000).map(|i| {
+            produced.set(produced.get() + 1);
+            format!("line {}", i)
+        });
+        let mut pipe = ClosingPipe {
+            capacity: 5,
+            lines: 0,
+        };
+
+        let e = write_lines(&mut pipe, lines).unwrap_err();
+        assert_eq!(e.kind(), std::io::ErrorKind::BrokenPipe);
+        assert_eq!(pipe.lines, 5);
+        // Only the line whose write failed is produced in vain
+        assert_eq!(produced.get(), 6);
+
+        let code = crate::error::exit_code(&AppError::from(e).into());
+        assert_eq!(code, crate::error::BROKEN_PIPE_EXIT_CODE);
+    }
+
+    #[test]
+    fn write_lines_writes_everything_to_open_output() {
+        let mut output = Vec::new();
+        let lines = ["a", "b"].map(str::to_owned);
+        assert_eq!(write_lines(&mut output, lines).unwrap(), 2);
+        assert_eq!(output, b"a\nb\n");
+    }
+
+    #[test]
+    fn parse_time_rejects_overflow() {
+        for s in ["99999999999999d", "9999999999999w", "9223372036854775807s"] {
+            assert!(matches!(parse_time(s), Err(AppError::InvalidTime(_))));
+        }
+        assert!(matches!(parse_time("3y"), Err(AppError::InvalidTime(_))));
+    }
+
+    #[test]
+    fn write_table_round_trips_through_csv_parser() {
+        let columns = ["path", "tags"];
+        let rows = vec![
+            vec!["plain.txt".to_owned(), "a;b".to_owned()],
+            vec!["with, comma.txt".to_owned(), "x,y;z".to_owned()],
+            vec!["with \"quotes\".txt".to_owned(), "\"quoted\"".to_owned()],
+            vec!["with\nnewline.txt".to_owned(), "multi\r\nline".to_owned()],
+            vec!["with\ttab.txt".to_owned(), String::new()],
+        ];
+
+        for delimiter in [b',', b'\t'] {
+            let mut output = Vec::new();
+            write_table(&mut output, delimiter, &columns, rows.clone())
+                .unwrap();
+
+            let mut reader = csv::ReaderBuilder::new()
+                .delimiter(delimiter)
+                .from_reader(output.as_slice());
+            assert_eq!(reader.headers().unwrap(), &columns[..]);
+            let parsed = reader
+                .records()
+                .map(|record| {
+                    record
+                        .unwrap()
+                        .iter()
+                        .map(str::to_owned)
+                        .collect()
+                })
+                .collect::<Vec<Vec<String>>>();
+            assert_eq!(parsed, rows);
+        }
+    }
+}